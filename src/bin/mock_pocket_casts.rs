@@ -0,0 +1,946 @@
+// A stand-in for the Pocket Casts API, to run the bot without a real account.
+//
+// Start it with `cargo run --bin mock_pocket_casts`, then point the bot at it with
+// POCKET_CASTS_API_URL=http://localhost:8081. Any email logs in with the password
+// "password", and every account gets its own empty files section.
+//
+// Failures are scripted as `<endpoint>:<mode>[:<times>]`, comma separated, either at start
+// with MOCK_FAILURES or while running with `POST /mock/failures`. e.g.
+// "upload:server_error:2,upload_status:failed" fails the next two uploads with a 500 and
+// every file's processing after that. Without a count the failure never goes away.
+//
+// Endpoints: login, token, subscription, usage, upload_request, upload, upload_status,
+// files, update, delete, up_next, or * for all of them.
+// Modes: unauthorized, forbidden, not_found, server_error, quota, too_large, expired,
+// failed, bad_response, delay=<seconds>.
+//
+// `GET /mock/state` shows every account's files and Up Next, `DELETE /mock/failures` clears
+// the script and `POST /mock/reset` starts over.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+use warp::{
+    http::StatusCode,
+    reply::{self, Response},
+    Buf, Filter, Reply,
+};
+
+fn default_port() -> u16 {
+    8081
+}
+
+fn default_password() -> String {
+    String::from("password")
+}
+
+fn default_total_size() -> u64 {
+    10 * 1024 * 1024 * 1024
+}
+
+fn default_token_lifetime() -> u64 {
+    60 * 60
+}
+
+fn default_upload_url_lifetime() -> u64 {
+    15 * 60
+}
+
+fn default_processing_checks() -> u32 {
+    1
+}
+
+// Read from MOCK_ prefixed environment variables, e.g. MOCK_PORT
+#[derive(Clone, Deserialize)]
+struct MockConfig {
+    #[serde(default = "default_port")]
+    port: u16,
+    // How the bot reaches this server, upload urls point here. Defaults to localhost.
+    base_url: Option<String>,
+    // Accepted for every email
+    #[serde(default = "default_password")]
+    password: String,
+    // Bytes of files storage each account gets
+    #[serde(default = "default_total_size")]
+    total_size: u64,
+    // Seconds until an access token expires, so refreshing can be tried
+    #[serde(default = "default_token_lifetime")]
+    token_lifetime: u64,
+    // Seconds an upload url stays valid
+    #[serde(default = "default_upload_url_lifetime")]
+    upload_url_lifetime: u64,
+    // How many status checks a file is still processing for once it's uploaded
+    #[serde(default = "default_processing_checks")]
+    processing_checks: u32,
+    #[serde(default)]
+    failures: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum FailureMode {
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    ServerError,
+    // The files storage is full
+    Quota,
+    TooLarge,
+    // The upload url ran out, as S3 reports it
+    Expired,
+    // Processing the upload failed, only for upload_status
+    Failed,
+    // A successful response the bot can't read
+    BadResponse,
+    // Seconds to wait before answering as usual
+    Delay(u64),
+}
+
+impl FailureMode {
+    fn parse(value: &str) -> Option<Self> {
+        if let Some(seconds) = value.strip_prefix("delay=") {
+            return seconds.parse().ok().map(FailureMode::Delay);
+        }
+        match value {
+            "unauthorized" => Some(FailureMode::Unauthorized),
+            "forbidden" => Some(FailureMode::Forbidden),
+            "not_found" => Some(FailureMode::NotFound),
+            "server_error" => Some(FailureMode::ServerError),
+            "quota" => Some(FailureMode::Quota),
+            "too_large" => Some(FailureMode::TooLarge),
+            "expired" => Some(FailureMode::Expired),
+            "failed" => Some(FailureMode::Failed),
+            "bad_response" => Some(FailureMode::BadResponse),
+            _ => None,
+        }
+    }
+
+    fn response(&self) -> Response {
+        match self {
+            FailureMode::Unauthorized => error_response(StatusCode::UNAUTHORIZED, "unauthorized"),
+            FailureMode::Forbidden => error_response(StatusCode::FORBIDDEN, "forbidden"),
+            FailureMode::NotFound => error_response(StatusCode::NOT_FOUND, "not_found"),
+            FailureMode::Quota => {
+                error_response(StatusCode::BAD_REQUEST, "files_storage_limit_exceeded")
+            }
+            FailureMode::TooLarge => error_response(StatusCode::PAYLOAD_TOO_LARGE, "too_large"),
+            FailureMode::Expired => reply::with_status(
+                "<Error><Code>AccessDenied</Code><Message>Request has expired</Message></Error>",
+                StatusCode::FORBIDDEN,
+            )
+            .into_response(),
+            FailureMode::BadResponse => reply::with_status("mock", StatusCode::OK).into_response(),
+            FailureMode::ServerError | FailureMode::Failed | FailureMode::Delay(_) => {
+                error_response(StatusCode::INTERNAL_SERVER_ERROR, "server_error")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Failure {
+    endpoint: String,
+    mode: FailureMode,
+    // How many more requests fail, None for all of them
+    remaining: Option<u32>,
+}
+
+// Every endpoint failures can be scripted for, "*" stands for all of them
+const ENDPOINTS: [&str; 12] = [
+    "login",
+    "token",
+    "subscription",
+    "usage",
+    "upload_request",
+    "upload",
+    "upload_status",
+    "files",
+    "update",
+    "delete",
+    "up_next",
+    "*",
+];
+
+// Parses "upload:server_error:2,usage:quota"
+fn parse_failures(script: &str) -> Result<Vec<Failure>, String> {
+    let mut failures = Vec::new();
+    for entry in script
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let parts: Vec<&str> = entry.split(':').collect();
+        // A typo would otherwise script nothing, and tests would pass without the failure
+        if !ENDPOINTS.contains(&parts[0]) {
+            return Err(format!("Unknown endpoint: {}", entry));
+        }
+        let mode = match parts.get(1).and_then(|mode| FailureMode::parse(mode)) {
+            Some(value) => value,
+            None => return Err(format!("Unknown failure: {}", entry)),
+        };
+        // A count of 0 would never run out
+        let remaining = match parts.get(2) {
+            Some(times) => match times.parse() {
+                Ok(0) | Err(_) => return Err(format!("Unknown failure: {}", entry)),
+                Ok(value) => Some(value),
+            },
+            None => None,
+        };
+        if parts.len() > 3 {
+            return Err(format!("Unknown failure: {}", entry));
+        }
+        failures.push(Failure {
+            endpoint: parts[0].to_string(),
+            mode,
+            remaining,
+        });
+    }
+    Ok(failures)
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MockFile {
+    uuid: String,
+    title: String,
+    // As announced in the upload request, then as received
+    size: u64,
+    content_type: String,
+    colour: u8,
+    published: String,
+    playing_status: u8,
+    played_up_to: f64,
+    starred: bool,
+    uploaded: bool,
+    // Status checks since it was uploaded
+    status_checks: u32,
+}
+
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Account {
+    files: Vec<MockFile>,
+    // File uuids, first is played next
+    up_next: Vec<String>,
+}
+
+impl Account {
+    fn used_size(&self) -> u64 {
+        self.files
+            .iter()
+            .filter(|file| file.uploaded)
+            .map(|file| file.size)
+            .sum()
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    accounts: HashMap<String, Account>,
+    failures: Vec<Failure>,
+    // For made up uuids
+    counter: u64,
+}
+
+impl MockState {
+    // Delays are waited out by the caller, anything else replaces the usual response
+    fn take_failure(&mut self, endpoint: &str) -> Option<FailureMode> {
+        let index = self
+            .failures
+            .iter()
+            .position(|failure| failure.endpoint == endpoint || failure.endpoint == "*")?;
+        let failure = &mut self.failures[index];
+        let mode = failure.mode;
+        if let Some(remaining) = failure.remaining.as_mut() {
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.failures.remove(index);
+            }
+        }
+        Some(mode)
+    }
+
+    fn new_uuid(&mut self) -> String {
+        self.counter += 1;
+        format!("00000000-0000-4000-8000-{:012x}", self.counter)
+    }
+
+    // Which account owns the upload, if any
+    fn find_file(&mut self, uuid: &str) -> Option<&mut MockFile> {
+        self.accounts
+            .values_mut()
+            .flat_map(|account| account.files.iter_mut())
+            .find(|file| file.uuid == uuid)
+    }
+}
+
+type Shared = Arc<Mutex<MockState>>;
+
+#[derive(Deserialize)]
+struct LoginRequest {
+    email: String,
+    password: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RefreshRequest {
+    refresh_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadRequest {
+    content_type: String,
+    title: String,
+    size: u64,
+}
+
+#[derive(Deserialize)]
+struct UploadQuery {
+    expires: u64,
+}
+
+#[derive(Deserialize)]
+struct FileUpdate {
+    uuid: String,
+    title: String,
+    colour: u8,
+}
+
+#[derive(Deserialize)]
+struct UpdateRequest {
+    files: Vec<FileUpdate>,
+}
+
+#[derive(Deserialize)]
+struct UpNextEpisode {
+    uuid: String,
+}
+
+#[derive(Deserialize)]
+struct UpNextRequest {
+    episode: UpNextEpisode,
+}
+
+#[derive(Deserialize)]
+struct TokenClaims {
+    exp: u64,
+    sub: String,
+}
+
+#[tokio::main]
+async fn main() {
+    dotenvy::dotenv().ok();
+
+    let config = envy::prefixed("MOCK_")
+        .from_env::<MockConfig>()
+        .expect("Failed to parse config parameters");
+    let failures = parse_failures(&config.failures).expect("Failed to parse MOCK_FAILURES");
+    let state: Shared = Arc::new(Mutex::new(MockState {
+        failures,
+        ..Default::default()
+    }));
+    println!(
+        "Starting mock Pocket Casts server on port {}...",
+        config.port
+    );
+    warp::serve(routes(state, config.clone()))
+        .run(([0, 0, 0, 0], config.port))
+        .await;
+}
+
+// Serves on a free local port with the default settings, for the bot's own tests
+#[cfg(test)]
+pub(crate) async fn start_for_tests(failures: &str) -> std::net::SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = MockConfig {
+        port: addr.port(),
+        base_url: Some(format!("http://{}", addr)),
+        password: default_password(),
+        total_size: default_total_size(),
+        token_lifetime: default_token_lifetime(),
+        upload_url_lifetime: default_upload_url_lifetime(),
+        processing_checks: default_processing_checks(),
+        failures: failures.to_string(),
+    };
+    let state: Shared = Arc::new(Mutex::new(MockState {
+        failures: parse_failures(failures).unwrap(),
+        ..Default::default()
+    }));
+    let incoming = futures_util::stream::unfold(listener, |listener| async move {
+        let connection = listener.accept().await.map(|(stream, _)| stream);
+        Some((connection, listener))
+    });
+    tokio::spawn(warp::serve(routes(state, config)).serve_incoming(incoming));
+    addr
+}
+
+fn routes(
+    state: Shared,
+    config: MockConfig,
+) -> impl Filter<Extract = (Response,), Error = warp::Rejection> + Clone {
+    let with_state = warp::any().map(move || state.clone());
+    let with_config = warp::any().map(move || config.clone());
+    let auth = warp::header::optional::<String>("authorization");
+
+    let login = warp::post()
+        .and(warp::path!("user" / "login_pocket_casts"))
+        .and(with_state.clone())
+        .and(with_config.clone())
+        .and(warp::body::json())
+        .and_then(login);
+    let token = warp::post()
+        .and(warp::path!("user" / "token"))
+        .and(with_state.clone())
+        .and(with_config.clone())
+        .and(warp::body::json())
+        .and_then(refresh);
+    let subscription = warp::post()
+        .and(warp::path!("subscription" / "status"))
+        .and(with_state.clone())
+        .and(auth)
+        .and_then(subscription_status);
+    let usage = warp::get()
+        .and(warp::path!("files" / "usage"))
+        .and(with_state.clone())
+        .and(with_config.clone())
+        .and(auth)
+        .and_then(usage);
+    let upload_request = warp::post()
+        .and(warp::path!("files" / "upload" / "request"))
+        .and(with_state.clone())
+        .and(with_config.clone())
+        .and(auth)
+        .and(warp::body::json())
+        .and_then(upload_request);
+    let upload = warp::put()
+        .and(warp::path!("mock" / "upload" / String))
+        .and(with_state.clone())
+        .and(warp::query::<UploadQuery>())
+        .and(warp::body::stream())
+        .and_then(upload);
+    let upload_status = warp::get()
+        .and(warp::path!("files" / "upload" / "status" / String))
+        .and(with_state.clone())
+        .and(with_config.clone())
+        .and(auth)
+        .and_then(upload_status);
+    let list_files = warp::get()
+        .and(warp::path!("files"))
+        .and(with_state.clone())
+        .and(auth)
+        .and_then(list_files);
+    let update_files = warp::post()
+        .and(warp::path!("files"))
+        .and(with_state.clone())
+        .and(auth)
+        .and(warp::body::json())
+        .and_then(update_files);
+    let delete_file = warp::delete()
+        .and(warp::path!("files" / String))
+        .and(with_state.clone())
+        .and(auth)
+        .and_then(delete_file);
+    let up_next = warp::post()
+        .and(warp::path!("up_next" / String))
+        .and(with_state.clone())
+        .and(auth)
+        .and(warp::body::json())
+        .and_then(up_next);
+
+    let show_state = warp::get()
+        .and(warp::path!("mock" / "state"))
+        .and(with_state.clone())
+        .and_then(show_state);
+    let add_failures = warp::post()
+        .and(warp::path!("mock" / "failures"))
+        .and(with_state.clone())
+        .and(warp::body::bytes())
+        .and_then(add_failures);
+    let clear_failures = warp::delete()
+        .and(warp::path!("mock" / "failures"))
+        .and(with_state.clone())
+        .and_then(clear_failures);
+    let reset = warp::post()
+        .and(warp::path!("mock" / "reset"))
+        .and(with_state)
+        .and_then(reset);
+
+    login
+        .or(token)
+        .unify()
+        .or(subscription)
+        .unify()
+        .or(usage)
+        .unify()
+        .or(upload_request)
+        .unify()
+        .or(upload)
+        .unify()
+        .or(upload_status)
+        .unify()
+        .or(list_files)
+        .unify()
+        .or(update_files)
+        .unify()
+        .or(delete_file)
+        .unify()
+        .or(up_next)
+        .unify()
+        .or(show_state)
+        .unify()
+        .or(add_failures)
+        .unify()
+        .or(clear_failures)
+        .unify()
+        .or(reset)
+        .unify()
+}
+
+// Pocket Casts style error, see check_response in the uploader
+fn error_response(status: StatusCode, message_id: &str) -> Response {
+    reply::with_status(
+        reply::json(&json!({
+            "errorMessage": format!("Mock {}", message_id),
+            "errorMessageId": message_id,
+        })),
+        status,
+    )
+    .into_response()
+}
+
+fn json_response<T: Serialize>(value: &T) -> Response {
+    reply::json(value).into_response()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Returns the scripted failure for the endpoint, after waiting out any delay
+async fn scripted(state: &Shared, endpoint: &str) -> Option<FailureMode> {
+    let mode = state.lock().await.take_failure(endpoint)?;
+    match mode {
+        FailureMode::Delay(seconds) => {
+            tokio::time::sleep(Duration::from_secs(seconds)).await;
+            None
+        }
+        mode => Some(mode),
+    }
+}
+
+// Looks like a JWT so the bot can read when it expires, but isn't signed
+fn issue_token(email: &str, lifetime: u64) -> String {
+    let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"none","typ":"JWT"}"#);
+    let claims = json!({
+        "exp": now() + lifetime,
+        "sub": format!("mock-{}", email),
+        "email": email,
+    });
+    let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+    format!("{}.{}.mock", header, payload)
+}
+
+fn session(email: &str, config: &MockConfig) -> Response {
+    json_response(&json!({
+        "accessToken": issue_token(email, config.token_lifetime),
+        "refreshToken": format!("refresh-{}", email),
+        "expiresIn": config.token_lifetime,
+        "email": email,
+    }))
+}
+
+// The account the bearer token belongs to. Tokens from elsewhere are accepted as they are,
+// so /auth works with anything, but expired ones from this server are refused.
+fn authorize(header: Option<String>) -> Option<String> {
+    let token = match header
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        Some(value) if !value.trim().is_empty() => value.trim().to_string(),
+        _ => return None,
+    };
+    let claims = token
+        .split('.')
+        .nth(1)
+        .and_then(|payload| URL_SAFE_NO_PAD.decode(payload).ok())
+        .and_then(|payload| serde_json::from_slice::<TokenClaims>(&payload).ok());
+    match claims {
+        Some(claims) if claims.exp < now() => None,
+        Some(claims) => Some(claims.sub),
+        None => Some(token),
+    }
+}
+
+async fn login(
+    state: Shared,
+    config: MockConfig,
+    request: LoginRequest,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "login").await {
+        return Ok(mode.response());
+    }
+    // Wrong credentials are a bad request
+    if request.password != config.password {
+        return Ok(error_response(
+            StatusCode::BAD_REQUEST,
+            "login_password_incorrect",
+        ));
+    }
+    Ok(session(&request.email, &config))
+}
+
+async fn refresh(
+    state: Shared,
+    config: MockConfig,
+    request: RefreshRequest,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "token").await {
+        return Ok(mode.response());
+    }
+    match request.refresh_token.strip_prefix("refresh-") {
+        Some(email) => Ok(session(email, &config)),
+        None => Ok(error_response(StatusCode::BAD_REQUEST, "invalid_grant")),
+    }
+}
+
+async fn subscription_status(state: Shared, auth: Option<String>) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "subscription").await {
+        return Ok(mode.response());
+    }
+    if authorize(auth).is_none() {
+        return Ok(FailureMode::Unauthorized.response());
+    }
+    Ok(json_response(&json!({ "paid": 1, "platform": 0 })))
+}
+
+async fn usage(
+    state: Shared,
+    config: MockConfig,
+    auth: Option<String>,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "usage").await {
+        return Ok(mode.response());
+    }
+    let account_id = match authorize(auth) {
+        Some(value) => value,
+        None => return Ok(FailureMode::Unauthorized.response()),
+    };
+    let mut state = state.lock().await;
+    let account = state.accounts.entry(account_id).or_default();
+    Ok(json_response(&json!({
+        "totalSize": config.total_size,
+        "usedSize": account.used_size(),
+        "totalFiles": account.files.iter().filter(|file| file.uploaded).count(),
+    })))
+}
+
+async fn upload_request(
+    state: Shared,
+    config: MockConfig,
+    auth: Option<String>,
+    request: UploadRequest,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "upload_request").await {
+        return Ok(mode.response());
+    }
+    let account_id = match authorize(auth) {
+        Some(value) => value,
+        None => return Ok(FailureMode::Unauthorized.response()),
+    };
+    let mut state = state.lock().await;
+    let uuid = state.new_uuid();
+    let account = state.accounts.entry(account_id).or_default();
+    if account.used_size() + request.size > config.total_size {
+        return Ok(FailureMode::Quota.response());
+    }
+    account.files.push(MockFile {
+        uuid: uuid.clone(),
+        title: request.title,
+        size: request.size,
+        content_type: request.content_type,
+        colour: 0,
+        published: chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        playing_status: 0,
+        played_up_to: 0.0,
+        starred: false,
+        uploaded: false,
+        status_checks: 0,
+    });
+    let base_url = config
+        .base_url
+        .clone()
+        .unwrap_or_else(|| format!("http://localhost:{}", config.port));
+    Ok(json_response(&json!({
+        "url": format!(
+            "{}/mock/upload/{}?expires={}",
+            base_url.trim_end_matches('/'),
+            uuid,
+            now() + config.upload_url_lifetime
+        ),
+        "fileUuid": uuid,
+    })))
+}
+
+// Stands in for the presigned S3 url, the contents are counted and thrown away
+async fn upload(
+    uuid: String,
+    state: Shared,
+    query: UploadQuery,
+    body: impl Stream<Item = Result<impl Buf, warp::Error>>,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "upload").await {
+        return Ok(mode.response());
+    }
+    if query.expires < now() {
+        return Ok(FailureMode::Expired.response());
+    }
+    let mut body = Box::pin(body);
+    let mut size = 0;
+    while let Some(chunk) = body.next().await {
+        match chunk {
+            Ok(chunk) => size += chunk.remaining() as u64,
+            Err(_) => return Ok(StatusCode::BAD_REQUEST.into_response()),
+        }
+    }
+    let mut state = state.lock().await;
+    match state.find_file(&uuid) {
+        Some(file) => {
+            file.size = size;
+            file.uploaded = true;
+            file.status_checks = 0;
+            Ok(StatusCode::OK.into_response())
+        }
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+async fn upload_status(
+    uuid: String,
+    state: Shared,
+    config: MockConfig,
+    auth: Option<String>,
+) -> Result<Response, Infallible> {
+    let failure = scripted(&state, "upload_status").await;
+    if let Some(mode) = failure.filter(|mode| *mode != FailureMode::Failed) {
+        return Ok(mode.response());
+    }
+    let account_id = match authorize(auth) {
+        Some(value) => value,
+        None => return Ok(FailureMode::Unauthorized.response()),
+    };
+    let mut state = state.lock().await;
+    let file = match state
+        .accounts
+        .get_mut(&account_id)
+        .and_then(|account| account.files.iter_mut().find(|file| file.uuid == uuid))
+    {
+        Some(value) => value,
+        None => return Ok(error_response(StatusCode::NOT_FOUND, "not_found")),
+    };
+    let status = if failure == Some(FailureMode::Failed) {
+        "failed"
+    } else if !file.uploaded {
+        "pending"
+    } else {
+        file.status_checks += 1;
+        if file.status_checks > config.processing_checks {
+            "ready"
+        } else {
+            "processing"
+        }
+    };
+    Ok(json_response(&json!({ "status": status })))
+}
+
+async fn list_files(state: Shared, auth: Option<String>) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "files").await {
+        return Ok(mode.response());
+    }
+    let account_id = match authorize(auth) {
+        Some(value) => value,
+        None => return Ok(FailureMode::Unauthorized.response()),
+    };
+    let mut state = state.lock().await;
+    let account = state.accounts.entry(account_id).or_default();
+    let files: Vec<&MockFile> = account.files.iter().filter(|file| file.uploaded).collect();
+    Ok(json_response(&json!({ "files": files })))
+}
+
+async fn update_files(
+    state: Shared,
+    auth: Option<String>,
+    request: UpdateRequest,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "update").await {
+        return Ok(mode.response());
+    }
+    let account_id = match authorize(auth) {
+        Some(value) => value,
+        None => return Ok(FailureMode::Unauthorized.response()),
+    };
+    let mut state = state.lock().await;
+    let account = state.accounts.entry(account_id).or_default();
+    for update in request.files {
+        match account
+            .files
+            .iter_mut()
+            .find(|file| file.uuid == update.uuid)
+        {
+            Some(file) => {
+                file.title = update.title;
+                file.colour = update.colour;
+            }
+            None => return Ok(error_response(StatusCode::NOT_FOUND, "not_found")),
+        }
+    }
+    Ok(json_response(&json!({})))
+}
+
+async fn delete_file(
+    uuid: String,
+    state: Shared,
+    auth: Option<String>,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "delete").await {
+        return Ok(mode.response());
+    }
+    let account_id = match authorize(auth) {
+        Some(value) => value,
+        None => return Ok(FailureMode::Unauthorized.response()),
+    };
+    let mut state = state.lock().await;
+    let account = state.accounts.entry(account_id).or_default();
+    let count = account.files.len();
+    account.files.retain(|file| file.uuid != uuid);
+    if account.files.len() == count {
+        return Ok(error_response(StatusCode::NOT_FOUND, "not_found"));
+    }
+    account.up_next.retain(|episode| *episode != uuid);
+    Ok(json_response(&json!({})))
+}
+
+async fn up_next(
+    path: String,
+    state: Shared,
+    auth: Option<String>,
+    request: UpNextRequest,
+) -> Result<Response, Infallible> {
+    if let Some(mode) = scripted(&state, "up_next").await {
+        return Ok(mode.response());
+    }
+    let account_id = match authorize(auth) {
+        Some(value) => value,
+        None => return Ok(FailureMode::Unauthorized.response()),
+    };
+    let mut state = state.lock().await;
+    let account = state.accounts.entry(account_id).or_default();
+    let uuid = request.episode.uuid;
+    if !account.files.iter().any(|file| file.uuid == uuid) {
+        return Ok(error_response(StatusCode::NOT_FOUND, "not_found"));
+    }
+    account.up_next.retain(|episode| *episode != uuid);
+    match path.as_str() {
+        "play_next" => account.up_next.insert(0, uuid),
+        "play_last" => account.up_next.push(uuid),
+        _ => return Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+    Ok(json_response(&json!({})))
+}
+
+async fn show_state(state: Shared) -> Result<Response, Infallible> {
+    let state = state.lock().await;
+    Ok(json_response(&json!({
+        "accounts": state.accounts,
+        "failures": state.failures,
+    })))
+}
+
+async fn add_failures(
+    state: Shared,
+    body: warp::hyper::body::Bytes,
+) -> Result<Response, Infallible> {
+    let script = String::from_utf8_lossy(&body);
+    match parse_failures(&script) {
+        Ok(failures) => {
+            let mut state = state.lock().await;
+            state.failures.extend(failures);
+            Ok(json_response(&json!({ "failures": state.failures })))
+        }
+        Err(message) => Ok(reply::with_status(message, StatusCode::BAD_REQUEST).into_response()),
+    }
+}
+
+async fn clear_failures(state: Shared) -> Result<Response, Infallible> {
+    state.lock().await.failures.clear();
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn reset(state: Shared) -> Result<Response, Infallible> {
+    *state.lock().await = MockState::default();
+    Ok(StatusCode::OK.into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_failures() {
+        let failures = parse_failures("upload:server_error:2, usage:delay=3").unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].endpoint, "upload");
+        assert_eq!(failures[0].mode, FailureMode::ServerError);
+        assert_eq!(failures[0].remaining, Some(2));
+        assert_eq!(failures[1].mode, FailureMode::Delay(3));
+        assert_eq!(failures[1].remaining, None);
+    }
+
+    #[test]
+    fn rejects_bad_failures() {
+        assert!(parse_failures("upload:server_error:0").is_err());
+        assert!(parse_failures("upload:server_error:-1").is_err());
+        assert!(parse_failures("upload:sometimes").is_err());
+        assert!(parse_failures("upload:server_error:1:2").is_err());
+        assert!(parse_failures("uplaod:server_error").is_err());
+    }
+
+    #[tokio::test]
+    async fn counted_failures_run_out() {
+        let addr = start_for_tests("login:server_error:1").await;
+        let client = reqwest::Client::new();
+        let login = || {
+            client
+                .post(format!("http://{}/user/login_pocket_casts", addr))
+                .json(&json!({ "email": "user@example.com", "password": "password" }))
+                .send()
+        };
+        assert_eq!(
+            login().await.unwrap().status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(login().await.unwrap().status(), StatusCode::OK);
+        let response = client
+            .post(format!("http://{}/mock/failures", addr))
+            .body("login:unauthorized:0")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(login().await.unwrap().status(), StatusCode::OK);
+    }
+}
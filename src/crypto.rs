@@ -0,0 +1,112 @@
+use std::env;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use once_cell::sync::Lazy;
+
+use crate::types::{BotError, BotErrorKind, BotResult};
+
+// 12 bytes for ChaCha20Poly1305
+const NONCE_LENGTH: usize = 12;
+
+// The key is a base64 encoded 32 byte value, e.g. `openssl rand -base64 32`
+static CIPHER: Lazy<Option<ChaCha20Poly1305>> = Lazy::new(|| {
+    let encoded_key = env::var("ENCRYPTION_KEY").ok()?;
+    let key = STANDARD.decode(encoded_key.trim()).ok()?;
+    if key.len() != 32 {
+        println!("Error: ENCRYPTION_KEY must be 32 bytes");
+        return None;
+    }
+    Some(ChaCha20Poly1305::new(Key::from_slice(&key)))
+});
+
+fn cipher() -> BotResult<&'static ChaCha20Poly1305> {
+    match CIPHER.as_ref() {
+        Some(cipher) => Ok(cipher),
+        None => Err(BotError::new(BotErrorKind::EncryptionError)),
+    }
+}
+
+// Returns the random nonce followed by the ciphertext
+pub fn encrypt(plaintext: &[u8]) -> BotResult<Vec<u8>> {
+    encrypt_with(cipher()?, plaintext)
+}
+
+pub fn decrypt(data: &[u8]) -> BotResult<Vec<u8>> {
+    decrypt_with(cipher()?, data)
+}
+
+fn encrypt_with(cipher: &ChaCha20Poly1305, plaintext: &[u8]) -> BotResult<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = match cipher.encrypt(&nonce, plaintext) {
+        Ok(value) => value,
+        Err(_) => return Err(BotError::new(BotErrorKind::EncryptionError)),
+    };
+    let mut output = nonce.to_vec();
+    output.extend(ciphertext);
+    Ok(output)
+}
+
+fn decrypt_with(cipher: &ChaCha20Poly1305, data: &[u8]) -> BotResult<Vec<u8>> {
+    if data.len() < NONCE_LENGTH {
+        return Err(BotError::new(BotErrorKind::EncryptionError));
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+    match cipher.decrypt(Nonce::from_slice(nonce), ciphertext) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(BotError::new(BotErrorKind::EncryptionError)),
+    }
+}
+
+// Hex encoded random value, for secrets that end up in urls
+pub fn random_token(length: usize) -> String {
+    let mut bytes = vec![0u8; length];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_cipher() -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&[7u8; 32]))
+    }
+
+    #[test]
+    fn decrypts_what_it_encrypts() {
+        let cipher = test_cipher();
+        let encrypted = encrypt_with(&cipher, b"# Netscape HTTP Cookie File").unwrap();
+        assert_ne!(&encrypted[NONCE_LENGTH..], b"# Netscape HTTP Cookie File");
+        assert_eq!(
+            decrypt_with(&cipher, &encrypted).unwrap(),
+            b"# Netscape HTTP Cookie File"
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_data() {
+        let cipher = test_cipher();
+        let encrypted = encrypt_with(&cipher, b"refresh token").unwrap();
+        for index in [0, NONCE_LENGTH, encrypted.len() - 1] {
+            let mut tampered = encrypted.clone();
+            tampered[index] ^= 1;
+            assert!(decrypt_with(&cipher, &tampered).is_err());
+        }
+        assert!(decrypt_with(&cipher, &encrypted[..NONCE_LENGTH - 1]).is_err());
+        let other_cipher = ChaCha20Poly1305::new(Key::from_slice(&[8u8; 32]));
+        assert!(decrypt_with(&other_cipher, &encrypted).is_err());
+    }
+
+    #[test]
+    fn encrypting_twice_gives_different_data() {
+        let cipher = test_cipher();
+        let first = encrypt_with(&cipher, b"secret key").unwrap();
+        let second = encrypt_with(&cipher, b"secret key").unwrap();
+        assert_ne!(first[..NONCE_LENGTH], second[..NONCE_LENGTH]);
+        assert_ne!(first, second);
+    }
+}
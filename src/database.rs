@@ -1,12 +1,11 @@
 use std::env;
 
-use redis::{aio::MultiplexedConnection, AsyncCommands, Client};
+use redis::{aio::MultiplexedConnection, AsyncCommands};
 
 use crate::types::{BotError, BotErrorKind, BotResult};
 
 #[derive(Clone)]
 pub struct Database {
-    pub client: Client,
     pub publish_conn: MultiplexedConnection,
     pub blocking_conn: MultiplexedConnection,
 }
@@ -19,7 +18,6 @@ impl Database {
         let blocking_conn = client.get_multiplexed_async_connection().await.unwrap();
 
         Database {
-            client,
             publish_conn,
             blocking_conn,
         }
@@ -56,6 +54,262 @@ impl Database {
         }
     }
 
+    pub async fn get_cookies(&mut self, user_id: String) -> BotResult<Option<Vec<u8>>> {
+        let id_string = format!("user-cookies:{}", user_id);
+        match self.publish_conn.get(id_string).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_cookies(&mut self, user_id: String, cookies: Vec<u8>) -> BotResult<()> {
+        let id_string = format!("user-cookies:{}", user_id);
+        match self
+            .publish_conn
+            .set::<String, Vec<u8>, String>(id_string, cookies)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn delete_cookies(&mut self, user_id: String) -> BotResult<()> {
+        let id_string = format!("user-cookies:{}", user_id);
+        match self.publish_conn.del::<String, i64>(id_string).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn get_destination_config(
+        &mut self,
+        user_id: String,
+        destination: &str,
+    ) -> BotResult<Option<Vec<u8>>> {
+        let id_string = format!("user-destination:{}:{}", user_id, destination);
+        match self.publish_conn.get(id_string).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_destination_config(
+        &mut self,
+        user_id: String,
+        destination: &str,
+        config: Vec<u8>,
+    ) -> BotResult<()> {
+        let id_string = format!("user-destination:{}:{}", user_id, destination);
+        match self
+            .publish_conn
+            .set::<String, Vec<u8>, String>(id_string, config)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn delete_destination_config(
+        &mut self,
+        user_id: String,
+        destination: &str,
+    ) -> BotResult<()> {
+        let id_string = format!("user-destination:{}:{}", user_id, destination);
+        match self.publish_conn.del::<String, i64>(id_string).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn get_refresh_token(&mut self, user_id: String) -> BotResult<Option<Vec<u8>>> {
+        let id_string = format!("user-refresh-token:{}", user_id);
+        match self.publish_conn.get(id_string).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_refresh_token(&mut self, user_id: String, token: Vec<u8>) -> BotResult<()> {
+        let id_string = format!("user-refresh-token:{}", user_id);
+        match self
+            .publish_conn
+            .set::<String, Vec<u8>, String>(id_string, token)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn delete_refresh_token(&mut self, user_id: String) -> BotResult<()> {
+        let id_string = format!("user-refresh-token:{}", user_id);
+        match self.publish_conn.del::<String, i64>(id_string).await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    // Named profiles only, the default profile uses the keys above as they are
+    pub async fn get_profiles(&mut self, user_id: String) -> BotResult<Vec<String>> {
+        let id_string = format!("user-profiles:{}", user_id);
+        match self.publish_conn.smembers(id_string).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_profile(
+        &mut self,
+        user_id: String,
+        profile: String,
+        exists: bool,
+    ) -> BotResult<()> {
+        let id_string = format!("user-profiles:{}", user_id);
+        let result = if exists {
+            self.publish_conn
+                .sadd::<String, String, i64>(id_string, profile)
+                .await
+        } else {
+            self.publish_conn
+                .srem::<String, String, i64>(id_string, profile)
+                .await
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    // Which profile an uploaded file went to, so its buttons act on the right account
+    pub async fn get_file_profile(
+        &mut self,
+        user_id: String,
+        uuid: &str,
+    ) -> BotResult<Option<String>> {
+        let id_string = format!("user-file-profiles:{}", user_id);
+        match self.publish_conn.hget(id_string, uuid).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_file_profile(
+        &mut self,
+        user_id: String,
+        uuid: &str,
+        profile: Option<&str>,
+    ) -> BotResult<()> {
+        let id_string = format!("user-file-profiles:{}", user_id);
+        let result = match profile {
+            Some(profile) => {
+                self.publish_conn
+                    .hset::<String, &str, &str, i64>(id_string, uuid, profile)
+                    .await
+            }
+            None => {
+                self.publish_conn
+                    .hdel::<String, &str, i64>(id_string, uuid)
+                    .await
+            }
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn get_setting(&mut self, user_id: String, name: &str) -> BotResult<Option<String>> {
+        let id_string = format!("user-settings:{}", user_id);
+        match self.publish_conn.hget(id_string, name).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_setting(
+        &mut self,
+        user_id: String,
+        name: &str,
+        value: String,
+    ) -> BotResult<()> {
+        let id_string = format!("user-settings:{}", user_id);
+        match self
+            .publish_conn
+            .hset::<String, &str, String, ()>(id_string, name, value)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn delete_setting(&mut self, user_id: String, name: &str) -> BotResult<()> {
+        let id_string = format!("user-settings:{}", user_id);
+        match self
+            .publish_conn
+            .hdel::<String, &str, i64>(id_string, name)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn get_housekeeping_users(&mut self) -> BotResult<Vec<String>> {
+        match self.publish_conn.smembers("housekeeping-users").await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_housekeeping_user(&mut self, user_id: String, enabled: bool) -> BotResult<()> {
+        let result = if enabled {
+            self.publish_conn
+                .sadd::<&str, String, i64>("housekeeping-users", user_id)
+                .await
+        } else {
+            self.publish_conn
+                .srem::<&str, String, i64>("housekeeping-users", user_id)
+                .await
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn get_protected_files(&mut self, user_id: String) -> BotResult<Vec<String>> {
+        let id_string = format!("user-protected-files:{}", user_id);
+        match self.publish_conn.smembers(id_string).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_protected_file(
+        &mut self,
+        user_id: String,
+        uuid: String,
+        protected: bool,
+    ) -> BotResult<()> {
+        let id_string = format!("user-protected-files:{}", user_id);
+        let result = if protected {
+            self.publish_conn
+                .sadd::<String, String, i64>(id_string, uuid)
+                .await
+        } else {
+            self.publish_conn
+                .srem::<String, String, i64>(id_string, uuid)
+                .await
+        };
+        match result {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
     pub async fn wait_for_request(&mut self) -> BotResult<(String, String)> {
         let timeout = 0;
         match self
@@ -79,11 +333,40 @@ impl Database {
         }
     }
 
+    pub async fn get_request_field(
+        &mut self,
+        key: String,
+        field: &str,
+    ) -> BotResult<Option<String>> {
+        match self.publish_conn.hget(key, field).await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn set_request_field(
+        &mut self,
+        key: String,
+        field: &str,
+        value: String,
+    ) -> BotResult<()> {
+        match self
+            .publish_conn
+            .hset::<String, &str, String, ()>(key, field, value)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
     pub async fn add_request(
         &mut self,
         user_id: String,
         chat_id: String,
         url: String,
+        // Optional details about the request, e.g. ("title", ...)
+        extra_fields: Vec<(&str, String)>,
     ) -> BotResult<()> {
         let processing_id: i64 = match self
             .publish_conn
@@ -96,12 +379,11 @@ impl Database {
         let processing_key = format!("yt_processing:{}", processing_id);
 
         // Add the request to the database, but the workers won't know about it just yet
+        let mut fields = vec![("user_id", user_id), ("chat_id", chat_id), ("url", url)];
+        fields.extend(extra_fields);
         match self
             .publish_conn
-            .hset_multiple::<&String, &str, String, ()>(
-                &processing_key,
-                &[("user_id", user_id), ("chat_id", chat_id), ("url", url)],
-            )
+            .hset_multiple::<&String, &str, String, ()>(&processing_key, &fields)
             .await
         {
             Ok(_) => (),
@@ -115,7 +397,77 @@ impl Database {
             .await
         {
             Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    // Parks the request until `check_at`, the status is kept so users are only notified of changes
+    pub async fn schedule_request(
+        &mut self,
+        processing_id: String,
+        status: String,
+        check_at: u64,
+    ) -> BotResult<()> {
+        self.set_request_field(processing_id.clone(), "scheduled", status)
+            .await?;
+        self.delay_request(processing_id, check_at).await
+    }
+
+    // Puts the request back on the queue at `check_at`, e.g. to retry after a failure
+    pub async fn delay_request(&mut self, processing_id: String, check_at: u64) -> BotResult<()> {
+        match self
+            .publish_conn
+            .zadd::<&str, u64, String, ()>("yt_scheduled", processing_id, check_at)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    // Returns how many times the request has been attempted, including this one
+    pub async fn increment_attempts(&mut self, processing_id: String) -> BotResult<u64> {
+        match self
+            .publish_conn
+            .hincr::<String, &str, u64, u64>(processing_id, "attempts", 1)
+            .await
+        {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn get_due_scheduled(&mut self, now: u64) -> BotResult<Vec<String>> {
+        match self
+            .publish_conn
+            .zrangebyscore::<&str, &str, u64, Vec<String>>("yt_scheduled", "-inf", now)
+            .await
+        {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
+        }
+    }
+
+    pub async fn requeue_scheduled(&mut self, processing_id: &String) -> BotResult<()> {
+        // Only whoever removes it from the schedule gets to requeue it
+        let removed = match self
+            .publish_conn
+            .zrem::<&str, &String, i64>("yt_scheduled", processing_id)
+            .await
+        {
+            Ok(value) => value,
             Err(_) => return Err(BotError::new(BotErrorKind::RedisError)),
+        };
+        if removed == 0 {
+            return Ok(());
+        }
+        match self
+            .publish_conn
+            .lpush::<&str, &String, ()>("yt_processing", processing_id)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(_) => Err(BotError::new(BotErrorKind::RedisError)),
         }
     }
 
@@ -0,0 +1,173 @@
+use std::{path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::Body;
+use teloxide::{
+    types::{InlineKeyboardButton, InlineKeyboardMarkup},
+    Bot,
+};
+use tokio::fs::File;
+use tokio_util::codec::{BytesCodec, FramedRead};
+
+use crate::{
+    bot::ConfigParameters,
+    database::Database,
+    downloader::{DownloadOptions, DownloadedMedia, MediaInfo},
+    feed::Feed,
+    types::{BotError, BotErrorKind, BotResult},
+};
+
+mod feed;
+mod pocket_casts;
+mod s3;
+mod telegram;
+mod webdav;
+
+pub use feed::FeedDestination;
+pub use pocket_casts::PocketCastsDestination;
+pub use s3::{S3Config, S3Destination};
+pub use telegram::TelegramDestination;
+pub use webdav::{WebDavConfig, WebDavDestination};
+
+// Used when the user hasn't picked any destinations
+pub const DEFAULT_DESTINATION: &str = "pocketcasts";
+
+// Ids accepted by /destinations, in the order they're listed
+pub const DESTINATION_IDS: [&str; 5] = ["pocketcasts", "feed", "telegram", "s3", "webdav"];
+
+// Everything about a job a destination may need
+pub struct Delivery<'a> {
+    pub processing_id: &'a str,
+    pub user_id: &'a str,
+    pub chat_id: &'a str,
+    // The link the media came from
+    pub url: &'a str,
+    pub info: &'a MediaInfo,
+    pub media: &'a DownloadedMedia,
+    pub options: &'a DownloadOptions,
+}
+
+// How a delivery went, for the job's report
+pub struct Delivered {
+    pub message: String,
+    // Sent along with the report, e.g. to delete what was uploaded
+    pub buttons: Vec<Vec<InlineKeyboardButton>>,
+}
+
+impl Delivered {
+    pub fn new(message: impl Into<String>) -> Self {
+        Delivered {
+            message: message.into(),
+            buttons: Vec::new(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Destination: Send + Sync {
+    // Used in the user's "destinations" setting, e.g. "pocketcasts"
+    fn id(&self) -> &'static str;
+
+    // Shown to the user, e.g. "Pocket Casts"
+    fn name(&self) -> &'static str;
+
+    // Delivers the downloaded media. The file is left where it is, other destinations may
+    // still need it.
+    async fn deliver(&self, delivery: &Delivery) -> BotResult<Delivered>;
+}
+
+pub fn from_config(
+    cfg: &ConfigParameters,
+    bot: Bot,
+    database: Database,
+) -> Vec<Arc<dyn Destination>> {
+    vec![
+        Arc::new(PocketCastsDestination::new(
+            cfg,
+            bot.clone(),
+            database.clone(),
+        )),
+        Arc::new(FeedDestination::new(Feed::new(database.clone(), cfg))),
+        Arc::new(TelegramDestination::new(bot)),
+        Arc::new(S3Destination::new(cfg, database.clone())),
+        Arc::new(WebDavDestination::new(cfg, database)),
+    ]
+}
+
+// Delivers to each of the user's destinations in turn and returns the report. Fails only when
+// every destination failed, so the request is retried without sending duplicates anywhere.
+pub async fn deliver_all(
+    destinations: &[Arc<dyn Destination>],
+    ids: &[String],
+    delivery: &Delivery<'_>,
+) -> BotResult<(String, InlineKeyboardMarkup)> {
+    let picked: Vec<&Arc<dyn Destination>> = destinations
+        .iter()
+        .filter(|destination| ids.iter().any(|id| id == destination.id()))
+        .collect();
+    if picked.is_empty() {
+        return Err(BotError::new(BotErrorKind::InvalidSettingError));
+    }
+
+    let mut results = Vec::new();
+    let mut buttons = Vec::new();
+    let mut first_error = None;
+    let mut failures = 0;
+    for destination in picked.iter() {
+        let message = match destination.deliver(delivery).await {
+            Ok(delivered) => {
+                buttons.extend(delivered.buttons);
+                delivered.message
+            }
+            Err(error) => {
+                let message = error.kind.failure_message().to_string();
+                first_error.get_or_insert(error);
+                failures += 1;
+                message
+            }
+        };
+        results.push((destination.name(), message));
+        // Every finished destination counts as progress
+        if let Some(heartbeat) = &delivery.options.heartbeat {
+            heartbeat.beat();
+        }
+    }
+    if failures == picked.len() {
+        if let Some(error) = first_error {
+            return Err(error);
+        }
+    }
+    // With a single destination its message is the whole report
+    let report = match results.as_slice() {
+        [(_, message)] => message.clone(),
+        _ => results
+            .iter()
+            .map(|(name, message)| format!("{}: {}", name, message))
+            .collect::<Vec<String>>()
+            .join("\n"),
+    };
+    Ok((report, InlineKeyboardMarkup::new(buttons)))
+}
+
+// Streams a file as a request body, with every chunk counting as progress
+pub fn file_body(file: File, options: &DownloadOptions) -> Body {
+    let heartbeat = options.heartbeat.clone();
+    let stream = FramedRead::new(file, BytesCodec::new()).inspect(move |_| {
+        if let Some(heartbeat) = &heartbeat {
+            heartbeat.beat();
+        }
+    });
+    Body::wrap_stream(stream)
+}
+
+// File name for destinations that keep the title, e.g. "Channel - Title.m4a"
+pub fn file_name(title: &str, file_path: &Path) -> String {
+    let extension = file_path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("m4a"));
+    // Slashes would end up as folders
+    let title = title.replace(['/', '\\'], "-");
+    format!("{}.{}", title.trim(), extension)
+}
@@ -0,0 +1,40 @@
+use async_trait::async_trait;
+
+use super::{Delivered, Delivery, Destination};
+use crate::{feed::Feed, types::BotResult};
+
+// The user's private podcast feed, served by the bot's webserver
+pub struct FeedDestination {
+    feed: Feed,
+}
+
+impl FeedDestination {
+    pub fn new(feed: Feed) -> Self {
+        FeedDestination { feed }
+    }
+}
+
+#[async_trait]
+impl Destination for FeedDestination {
+    fn id(&self) -> &'static str {
+        "feed"
+    }
+
+    fn name(&self) -> &'static str {
+        "Feed"
+    }
+
+    // Feeds have no size limits, so the file is kept whole along with its chapters
+    async fn deliver(&self, delivery: &Delivery) -> BotResult<Delivered> {
+        self.feed
+            .clone()
+            .add_episode(
+                delivery.user_id,
+                delivery.media,
+                delivery.info,
+                delivery.url,
+            )
+            .await?;
+        Ok(Delivered::new("Done! It's in your /feed."))
+    }
+}
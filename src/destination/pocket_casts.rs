@@ -0,0 +1,243 @@
+use std::{path::PathBuf, time::Duration};
+
+use async_trait::async_trait;
+use teloxide::{requests::Requester, Bot};
+
+use super::{Delivered, Delivery, Destination};
+use crate::{
+    bot::ConfigParameters,
+    database::Database,
+    files,
+    process::Timeouts,
+    splitter,
+    types::{BotError, BotErrorKind, BotResult},
+    uploader::{self, PocketCastsClient, UpNextPosition, UploadedFile},
+    user::{self, User},
+};
+
+// Warn when an upload leaves the files storage fuller than this
+const QUOTA_WARNING_FRACTION: f64 = 0.9;
+
+// The user's files section, split into parts when it's too long for one file
+pub struct PocketCastsDestination {
+    bot: Bot,
+    database: Database,
+    pocket_casts: PocketCastsClient,
+    timeouts: Timeouts,
+    // Seconds, longer media is uploaded in parts. 0 never splits.
+    max_part_duration: u64,
+    processing_timeout: Duration,
+    processing_poll_interval: Duration,
+}
+
+impl PocketCastsDestination {
+    pub fn new(cfg: &ConfigParameters, bot: Bot, database: Database) -> Self {
+        PocketCastsDestination {
+            bot,
+            database,
+            pocket_casts: PocketCastsClient::new(cfg),
+            timeouts: Timeouts::new(cfg),
+            max_part_duration: cfg.max_part_duration,
+            processing_timeout: Duration::from_secs(cfg.processing_timeout),
+            processing_poll_interval: Duration::from_secs(cfg.processing_poll_interval),
+        }
+    }
+
+    // Refuses uploads that won't fit in the user's files storage, and warns when it's nearly
+    // full. The upload is still tried if the usage can't be fetched, unless the token was refused.
+    async fn check_quota(&self, chat_id: &str, token: &str, parts: &[PathBuf]) -> BotResult<()> {
+        let usage = match self.pocket_casts.usage(token).await {
+            Ok(value) => value,
+            Err(error) if matches!(error.kind, BotErrorKind::UnauthorizedError) => {
+                return Err(error)
+            }
+            Err(_) => return Ok(()),
+        };
+        let mut upload_size = 0;
+        for part_path in parts.iter() {
+            upload_size += tokio::fs::metadata(part_path).await?.len();
+        }
+        if upload_size > usage.remaining_size() {
+            return Err(BotError::new(BotErrorKind::QuotaExceededError));
+        }
+        let used_after = usage.used_size + upload_size;
+        if used_after as f64 >= usage.total_size as f64 * QUOTA_WARNING_FRACTION {
+            let output = format!(
+                "Your Pocket Casts files storage is almost full, {} of {} will be used after this upload.",
+                uploader::format_size(used_after),
+                uploader::format_size(usage.total_size)
+            );
+            self.bot.send_message(chat_id.to_string(), output).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Destination for PocketCastsDestination {
+    fn id(&self) -> &'static str {
+        "pocketcasts"
+    }
+
+    fn name(&self) -> &'static str {
+        "Pocket Casts"
+    }
+
+    // Uploads the media and waits for Pocket Casts to process it
+    async fn deliver(&self, delivery: &Delivery) -> BotResult<Delivered> {
+        let mut database = self.database.clone();
+        let options = delivery.options;
+        let media = delivery.media;
+        // Requests queued before profiles existed go to the default profile
+        let profile = database
+            .get_request_field(delivery.processing_id.to_string(), "profile")
+            .await?
+            .unwrap_or_else(|| user::DEFAULT_PROFILE.to_string());
+        let account = user::account_id(delivery.user_id, &profile);
+
+        let parts = splitter::split_media(
+            &media.file_path,
+            delivery.info.duration,
+            delivery.info.chapters.as_deref().unwrap_or_default(),
+            self.max_part_duration as f64,
+            self.timeouts.postprocess,
+            options.heartbeat.as_ref(),
+        )
+        .await?;
+        self.bot
+            .send_message(delivery.chat_id.to_string(), String::from("Uploading..."))
+            .await?;
+        // Starting the next stage counts as progress
+        if let Some(heartbeat) = &options.heartbeat {
+            heartbeat.beat();
+        }
+        // Downloading and splitting can take long enough for the token to expire
+        let mut token =
+            User::get_fresh_token(&mut database, &self.pocket_casts, account.clone()).await?;
+        if let Err(error) = self.check_quota(delivery.chat_id, &token, &parts).await {
+            if !matches!(error.kind, BotErrorKind::UnauthorizedError) {
+                return Err(error);
+            }
+            // It may have been revoked or expired early, a new one is worth one more try
+            token = User::refresh_token(&mut database, &self.pocket_casts, account).await?;
+            self.check_quota(delivery.chat_id, &token, &parts).await?;
+        }
+        let token = token.as_str();
+        let mut files = Vec::new();
+        if parts.len() == 1 {
+            files.push(
+                self.pocket_casts
+                    .upload_media(
+                        token,
+                        &media.title,
+                        &media.file_path,
+                        options.heartbeat.as_ref(),
+                    )
+                    .await?,
+            );
+        } else {
+            for (index, part_path) in parts.iter().enumerate() {
+                let part_title = format!("{} (Part {}/{})", media.title, index + 1, parts.len());
+                let result = self
+                    .pocket_casts
+                    .upload_media(token, &part_title, part_path, options.heartbeat.as_ref())
+                    .await;
+                tokio::fs::remove_file(part_path).await.ok();
+                match result {
+                    Ok(file) => files.push(file),
+                    Err(error) => {
+                        // Don't leave an incomplete set of parts behind in the user's files
+                        for file in files.iter() {
+                            self.pocket_casts.delete_file(token, &file.uuid).await.ok();
+                        }
+                        for part_path in parts.iter().skip(index + 1) {
+                            tokio::fs::remove_file(part_path).await.ok();
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+        }
+        // Keep track of where the upload ended up in the user's files
+        let file_uuids = files
+            .iter()
+            .map(|file| file.uuid.as_str())
+            .collect::<Vec<&str>>()
+            .join(",");
+        database
+            .set_request_field(delivery.processing_id.to_string(), "files", file_uuids)
+            .await?;
+        // The Delete buttons on the report may be pressed after switching profiles
+        for file in files.iter() {
+            database
+                .set_file_profile(delivery.user_id.to_string(), &file.uuid, Some(&profile))
+                .await
+                .ok();
+        }
+        // The file can only be played once Pocket Casts is done processing it
+        let mut processed = true;
+        for file in files.iter() {
+            let result = self
+                .pocket_casts
+                .wait_until_processed(
+                    token,
+                    &file.uuid,
+                    self.processing_timeout,
+                    self.processing_poll_interval,
+                    options.heartbeat.as_ref(),
+                )
+                .await;
+            match result {
+                Ok(value) => processed &= value,
+                Err(error) => {
+                    // Don't leave a broken file behind in the user's files
+                    if matches!(error.kind, BotErrorKind::ProcessingError) {
+                        for file in files.iter() {
+                            self.pocket_casts.delete_file(token, &file.uuid).await.ok();
+                        }
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        let mut output = if processed {
+            String::from("Done!")
+        } else {
+            String::from("Uploaded, but Pocket Casts is still processing it. It should show up in your files soon.")
+        };
+        // The upload itself went fine, so nothing to do with Up Next fails the request
+        let up_next = match User::get_up_next(&mut database, delivery.user_id.to_string()).await {
+            Ok(value) => value,
+            Err(_) => {
+                output = format!("{} Unable to check your Up Next setting though.", output);
+                None
+            }
+        };
+        if let Some(position) = up_next {
+            if processed {
+                // Adding to the top one by one would reverse the parts
+                let ordered: Vec<&UploadedFile> = match position {
+                    UpNextPosition::Top => files.iter().rev().collect(),
+                    UpNextPosition::Bottom => files.iter().collect(),
+                };
+                for file in ordered {
+                    if self
+                        .pocket_casts
+                        .add_to_up_next(token, file, position)
+                        .await
+                        .is_err()
+                    {
+                        output = format!("{} Unable to add it to Up Next though.", output);
+                        break;
+                    }
+                }
+            } else {
+                output = format!("{} It wasn't added to Up Next.", output);
+            }
+        }
+        Ok(Delivered {
+            message: output,
+            buttons: files::delete_buttons(&files),
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use teloxide::{
+    payloads::{SendAudioSetters, SendVideoSetters},
+    requests::Requester,
+    types::InputFile,
+    Bot,
+};
+
+use super::{Delivered, Delivery, Destination};
+use crate::types::{BotError, BotErrorKind, BotResult};
+
+// Bots can't send files larger than 50 MB
+const MAX_FILE_SIZE: u64 = 50 * 1024 * 1024;
+
+// Sends the media back to the chat it was requested from
+pub struct TelegramDestination {
+    bot: Bot,
+}
+
+impl TelegramDestination {
+    pub fn new(bot: Bot) -> Self {
+        TelegramDestination { bot }
+    }
+}
+
+#[async_trait]
+impl Destination for TelegramDestination {
+    fn id(&self) -> &'static str {
+        "telegram"
+    }
+
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    async fn deliver(&self, delivery: &Delivery) -> BotResult<Delivered> {
+        let media = delivery.media;
+        let file_size = tokio::fs::metadata(&media.file_path).await?.len();
+        if file_size > MAX_FILE_SIZE {
+            return Err(BotError::new(BotErrorKind::FileTooLargeError));
+        }
+        let file_name = super::file_name(&media.title, &media.file_path);
+        let file = InputFile::file(&media.file_path).file_name(file_name);
+        let duration = delivery
+            .info
+            .duration
+            .map(|duration| duration.round() as u32);
+        let chat_id = delivery.chat_id.to_string();
+        // Only video mode produces MP4 files
+        if is_video(&media.file_path) {
+            let mut request = self
+                .bot
+                .send_video(chat_id, file)
+                .caption(&media.title)
+                .supports_streaming(true);
+            if let Some(duration) = duration {
+                request = request.duration(duration);
+            }
+            request.await?;
+        } else {
+            let mut request = self.bot.send_audio(chat_id, file).title(&media.title);
+            if let Some(channel) = &delivery.info.channel {
+                request = request.performer(channel);
+            }
+            if let Some(duration) = duration {
+                request = request.duration(duration);
+            }
+            request.await?;
+        }
+        Ok(Delivered::new("Sent."))
+    }
+}
+
+fn is_video(file_path: &Path) -> bool {
+    file_path
+        .extension()
+        .map(|extension| extension.eq_ignore_ascii_case("mp4"))
+        .unwrap_or(false)
+}
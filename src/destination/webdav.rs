@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use reqwest::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Client, Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+
+use super::{Delivered, Delivery, Destination};
+use crate::{
+    bot::ConfigParameters,
+    database::Database,
+    types::{BotError, BotErrorKind, BotResult},
+    uploader,
+    user::User,
+};
+
+// A folder on a WebDAV server, e.g. Nextcloud or an Audiobookshelf library
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct WebDavConfig {
+    // e.g. "https://cloud.example.com/remote.php/dav/files/me/Podcasts"
+    pub url: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl WebDavConfig {
+    // Parses "/webdav <folder url> <username> <password>"
+    pub fn parse(input: &str) -> BotResult<Self> {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        if words.len() != 3 || Url::parse(words[0]).is_err() {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        Ok(WebDavConfig {
+            url: words[0].trim_end_matches('/').to_string(),
+            username: words[1].to_string(),
+            password: words[2].to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for WebDavConfig {
+    // Everything but the password
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} as {}", self.url, self.username)
+    }
+}
+
+// A folder the user configured with /webdav
+pub struct WebDavDestination {
+    client: Client,
+    database: Database,
+    upload_min_timeout: Duration,
+    upload_min_speed: u64,
+}
+
+impl WebDavDestination {
+    pub fn new(cfg: &ConfigParameters, database: Database) -> Self {
+        WebDavDestination {
+            client: Client::new(),
+            database,
+            upload_min_timeout: Duration::from_secs(cfg.upload_min_timeout),
+            upload_min_speed: cfg.upload_min_speed.max(1),
+        }
+    }
+}
+
+#[async_trait]
+impl Destination for WebDavDestination {
+    fn id(&self) -> &'static str {
+        "webdav"
+    }
+
+    fn name(&self) -> &'static str {
+        "WebDAV"
+    }
+
+    async fn deliver(&self, delivery: &Delivery) -> BotResult<Delivered> {
+        let config: WebDavConfig = match User::get_destination_config(
+            &mut self.database.clone(),
+            delivery.user_id.to_string(),
+            self.id(),
+        )
+        .await?
+        {
+            Some(value) => value,
+            None => return Err(BotError::new(BotErrorKind::InvalidSettingError)),
+        };
+        let media = delivery.media;
+        let file_name = super::file_name(&media.title, &media.file_path);
+        let mut url = match Url::parse(&format!("{}/", config.url)) {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::new(BotErrorKind::InvalidSettingError)),
+        };
+        // Takes care of encoding the name
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(&file_name);
+        }
+        let file_size = tokio::fs::metadata(&media.file_path).await?.len();
+        let file = File::open(&media.file_path).await?;
+        let timeout =
+            self.upload_min_timeout + Duration::from_secs(file_size / self.upload_min_speed);
+        let response = self
+            .client
+            .put(url)
+            .timeout(timeout)
+            .basic_auth(&config.username, Some(&config.password))
+            .header(CONTENT_TYPE, uploader::content_type(&media.file_path))
+            .header(CONTENT_LENGTH, file_size)
+            .body(super::file_body(file, delivery.options))
+            .send()
+            .await?;
+        match response.status().as_u16() {
+            200..=299 => Ok(Delivered::new(format!("Saved as {}.", file_name))),
+            401 | 403 => Err(BotError::new(BotErrorKind::ForbiddenError)),
+            // The folder doesn't exist
+            404 | 409 => Err(BotError::new(BotErrorKind::InvalidSettingError)),
+            413 => Err(BotError::new(BotErrorKind::FileTooLargeError)),
+            507 => Err(BotError::new(BotErrorKind::QuotaExceededError)),
+            _ => Err(BotError::new(BotErrorKind::UploadError)),
+        }
+    }
+}
@@ -1,72 +1,311 @@
 use std::{
     path::{Path, PathBuf},
-    process::{Output, Stdio},
+    process::Command,
+    sync::Arc,
+    time::Duration,
 };
 
-use tokio::process::Command;
-
-use crate::types::{BotError, BotErrorKind, BotResult};
-
-pub async fn download_audio(url: &String) -> BotResult<(String, PathBuf)> {
-    // Don't download yet, only get the title of video to use later
-    let dry_run_args = vec!["--simulate", "--print", "%(channel)s - %(title)s"];
-    let dry_run_output = run_yt_dlp(&url, dry_run_args).await?;
-    // The string from stdout has a newline at the end we don't want
-    let file_title = String::from_utf8(dry_run_output.stdout)?.replace("\n", "");
-
-    // Download the video using the video ID as the filename
-    let download_args = vec![
-        "--no-simulate",
-        "--verbose",
-        "--print",
-        "after_move:filepath",
-    ];
-    let download_output = run_yt_dlp(&url, download_args).await?;
-    // The string from stdout has a newline at the end we don't want
-    let file_path_string = String::from_utf8(download_output.stdout)?.replace("\n", "");
-    let file_path = PathBuf::from(file_path_string);
-
-    Ok((file_title, file_path))
+use async_trait::async_trait;
+use serde::Deserialize;
+use teloxide::Bot;
+
+use crate::{
+    bot::ConfigParameters,
+    process::{self, Timeouts},
+    subtitles::{self, SubtitlePreference},
+    titles::TitleFormat,
+    types::{BotError, BotErrorKind, BotResult},
+    watchdog::Heartbeat,
+};
+
+mod fake;
+mod http;
+pub mod telegram;
+mod ytdlp;
+
+pub use fake::FakeDownloader;
+pub use http::HttpDownloader;
+pub use telegram::TelegramDownloader;
+pub use ytdlp::{YtDlp, YtDlpBinary};
+
+pub const DOWNLOAD_PATH: &str = "/tmp/.cache";
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Chapter {
+    // In seconds
+    pub start_time: f64,
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MediaInfo {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub channel: Option<String>,
+    // "is_upcoming", "is_live", "post_live", "was_live" or "not_live"
+    #[serde(default)]
+    pub live_status: Option<String>,
+    #[serde(default)]
+    pub is_live: Option<bool>,
+    // When a premiere or scheduled stream is supposed to start
+    #[serde(default)]
+    pub release_timestamp: Option<u64>,
+    // In seconds
+    #[serde(default)]
+    pub duration: Option<f64>,
+    #[serde(default)]
+    pub chapters: Option<Vec<Chapter>>,
+    // e.g. "20230304"
+    #[serde(default)]
+    pub upload_date: Option<String>,
+    #[serde(default)]
+    pub playlist_index: Option<u64>,
+    // Url of the video's thumbnail, used as episode artwork in feeds
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+}
+
+// Per-job settings, on top of whatever the backend does by default
+#[derive(Clone, Debug, Default)]
+pub struct DownloadOptions {
+    // Netscape cookies file, only set for users who have uploaded one
+    pub cookies_path: Option<PathBuf>,
+    // Proxy url picked for this job, None to connect directly
+    pub proxy: Option<String>,
+    // Only set for users who want a transcript along with the audio
+    pub subtitles: Option<SubtitlePreference>,
+    // Set when video is wanted instead of audio, the highest resolution to download
+    pub video_max_height: Option<u64>,
+    // How the user wants the upload named
+    pub title_format: TitleFormat,
+    // Lets the watchdog know the job is still making progress
+    pub heartbeat: Option<Heartbeat>,
+}
+
+#[async_trait]
+pub trait Downloader: Send + Sync {
+    // Only gets information about the media, nothing is downloaded yet
+    async fn fetch_info(&self, url: &str, options: &DownloadOptions) -> BotResult<MediaInfo>;
+
+    // Downloads the media as an audio file, or a video file in video mode, and returns where it
+    // was saved
+    async fn download_media(
+        &self,
+        url: &str,
+        info: &MediaInfo,
+        options: &DownloadOptions,
+    ) -> BotResult<PathBuf>;
+
+    // Downloads subtitles, or auto-generated captions, as an SRT file. Returns None when
+    // there aren't any in the requested language, or the backend doesn't support them.
+    async fn download_subtitles(
+        &self,
+        _url: &str,
+        _info: &MediaInfo,
+        _language: &str,
+        _options: &DownloadOptions,
+    ) -> BotResult<Option<PathBuf>> {
+        Ok(None)
+    }
+}
+
+pub struct DownloadedMedia {
+    pub title: String,
+    pub file_path: PathBuf,
+    // Already converted to the user's preferred format
+    pub subtitles_path: Option<PathBuf>,
+}
+
+pub async fn download_media(
+    downloader: &dyn Downloader,
+    url: &str,
+    info: &MediaInfo,
+    options: &DownloadOptions,
+) -> BotResult<DownloadedMedia> {
+    let file_path = downloader.download_media(url, info, options).await?;
+    // Missing subtitles shouldn't fail the whole request, the media is what matters
+    let subtitles_path = match &options.subtitles {
+        Some(preference) => match downloader
+            .download_subtitles(url, info, &preference.language, options)
+            .await
+        {
+            Ok(Some(srt_path)) => subtitles::convert(&srt_path, preference).await.ok(),
+            _ => None,
+        },
+        None => None,
+    };
+    Ok(DownloadedMedia {
+        title: options.title_format.render(info),
+        file_path,
+        subtitles_path,
+    })
+}
+
+// Sends direct links to media files straight over HTTP, files sent to the bot through the
+// Bot API, and everything else goes through yt-dlp
+pub struct AutoDownloader {
+    yt_dlp: YtDlp,
+    http: HttpDownloader,
+    telegram: TelegramDownloader,
+}
+
+impl AutoDownloader {
+    pub fn new(yt_dlp: YtDlp, http: HttpDownloader, telegram: TelegramDownloader) -> Self {
+        AutoDownloader {
+            yt_dlp,
+            http,
+            telegram,
+        }
+    }
+
+    fn backend(&self, url: &str) -> &dyn Downloader {
+        if TelegramDownloader::is_telegram_url(url) {
+            &self.telegram
+        } else if HttpDownloader::is_media_url(url) {
+            &self.http
+        } else {
+            &self.yt_dlp
+        }
+    }
+}
+
+#[async_trait]
+impl Downloader for AutoDownloader {
+    async fn fetch_info(&self, url: &str, options: &DownloadOptions) -> BotResult<MediaInfo> {
+        self.backend(url).fetch_info(url, options).await
+    }
+
+    async fn download_media(
+        &self,
+        url: &str,
+        info: &MediaInfo,
+        options: &DownloadOptions,
+    ) -> BotResult<PathBuf> {
+        self.backend(url).download_media(url, info, options).await
+    }
+
+    async fn download_subtitles(
+        &self,
+        url: &str,
+        info: &MediaInfo,
+        language: &str,
+        options: &DownloadOptions,
+    ) -> BotResult<Option<PathBuf>> {
+        self.backend(url)
+            .download_subtitles(url, info, language, options)
+            .await
+    }
 }
 
-async fn run_yt_dlp(url: &String, custom_args: Vec<&str>) -> BotResult<Output> {
-    let yt_dlp_path = Path::new("yt-dlp");
-    let download_path = Path::new("/tmp/.cache");
-    let default_args = vec![
-        "--quiet",
-        "--no-warnings",
-        "--format",
-        "bestaudio",
-        "--extract-audio",
-        "--audio-format",
-        "m4a",
-        "--add-metadata",
-        // TODO: Figure out why this works in local container and fails in fly.io container
-        //"--embed-thumbnail",
-        "--output",
-        "%(id)s.%(ext)s",
-    ];
-    let mut command = Command::new(yt_dlp_path);
+pub fn from_config(
+    cfg: &ConfigParameters,
+    bot: Bot,
+    yt_dlp_binary: YtDlpBinary,
+) -> Arc<dyn Downloader> {
+    match cfg.downloader_backend.as_str() {
+        "fake" => {
+            let fixtures_path = cfg
+                .fake_fixtures_path
+                .as_ref()
+                .expect("FAKE_FIXTURES_PATH is required for the fake downloader");
+            Arc::new(FakeDownloader::from_dir(fixtures_path).expect("Failed to load fake fixtures"))
+        }
+        _ => Arc::new(AutoDownloader::new(
+            YtDlp::new(cfg, yt_dlp_binary),
+            HttpDownloader::new(Timeouts::new(cfg)),
+            TelegramDownloader::new(bot, Timeouts::new(cfg)),
+        )),
+    }
+}
+
+// Files with these extensions never have a picture, so they stay audio even in video mode
+const AUDIO_EXTENSIONS: [&str; 8] = ["aac", "flac", "m4a", "mp3", "oga", "ogg", "opus", "wav"];
+
+// Converts the file into something Pocket Casts can play, an MP4 video in video mode or
+// M4A audio otherwise
+pub async fn transcode(
+    file_path: &Path,
+    options: &DownloadOptions,
+    timeout: Duration,
+) -> BotResult<PathBuf> {
+    let is_audio = file_path
+        .extension()
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false);
+    match options.video_max_height {
+        Some(max_height) if !is_audio => {
+            transcode_to_mp4(file_path, max_height, timeout, options.heartbeat.as_ref()).await
+        }
+        _ => transcode_to_m4a(file_path, timeout, options.heartbeat.as_ref()).await,
+    }
+}
+
+// Pocket Casts is uploaded to as "audio/mp4", so anything else gets converted first
+pub async fn transcode_to_m4a(
+    file_path: &Path,
+    timeout: Duration,
+    heartbeat: Option<&Heartbeat>,
+) -> BotResult<PathBuf> {
+    let is_m4a = file_path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("m4a"))
+        .unwrap_or(false);
+    if is_m4a {
+        return Ok(file_path.to_path_buf());
+    }
+    let output_path = file_path.with_extension("m4a");
+    let mut command = Command::new("ffmpeg");
     command
-        .current_dir(download_path)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-    for arg in default_args.into_iter() {
-        command.arg(arg);
+        .arg("-y")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-i")
+        .arg(file_path)
+        .args(["-vn", "-c:a", "aac", "-b:a", "128k"])
+        .arg(&output_path);
+    let output = process::run(command, timeout, heartbeat).await?;
+    if !output.status.success() {
+        return Err(BotError::new(BotErrorKind::DownloadError));
     }
-    for arg in custom_args.into_iter() {
-        command.arg(arg);
+    tokio::fs::remove_file(file_path).await?;
+    Ok(output_path)
+}
+
+// Videos are uploaded as "video/mp4", anything else is re-encoded to H.264/AAC no taller than
+// `max_height`
+pub async fn transcode_to_mp4(
+    file_path: &Path,
+    max_height: u64,
+    timeout: Duration,
+    heartbeat: Option<&Heartbeat>,
+) -> BotResult<PathBuf> {
+    let is_mp4 = file_path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("mp4"))
+        .unwrap_or(false);
+    if is_mp4 {
+        return Ok(file_path.to_path_buf());
     }
-    // Make sure the source url is the last argument
-    command.arg(url);
-
-    match command.spawn()?.wait_with_output().await {
-        Ok(output) => Ok(output),
-        Err(_) => Err(BotError::new(BotErrorKind::DownloadError)),
-        // panic!(
-        //     "downloading video failed:\nstdout: {}\nstderr: {}",
-        //     String::from_utf8(output.stdout)?,
-        //     String::from_utf8(output.stderr)?
-        // );
+    let output_path = file_path.with_extension("mp4");
+    let mut command = Command::new("ffmpeg");
+    command
+        .arg("-y")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-i")
+        .arg(file_path)
+        .arg("-vf")
+        .arg(format!("scale=-2:'min({},ih)'", max_height))
+        .args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23"])
+        .args(["-c:a", "aac", "-b:a", "128k", "-movflags", "+faststart"])
+        .arg(&output_path);
+    let output = process::run(command, timeout, heartbeat).await?;
+    if !output.status.success() {
+        return Err(BotError::new(BotErrorKind::DownloadError));
     }
+    tokio::fs::remove_file(file_path).await?;
+    Ok(output_path)
 }
@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::types::{BotError, BotErrorKind, BotResult};
+
+use super::{DownloadOptions, Downloader, MediaInfo, DOWNLOAD_PATH};
+
+#[derive(Clone, Deserialize)]
+struct Fixture {
+    url: String,
+    info: MediaInfo,
+    // Relative to the fixtures folder
+    file: PathBuf,
+}
+
+// Serves canned metadata and local audio files, so the queue can run without network access.
+//
+// Fixtures are read from `fixtures.json` in the given folder:
+// [{ "url": "https://youtu.be/abc", "info": { "id": "abc", "title": "Title" }, "file": "abc.m4a" }]
+pub struct FakeDownloader {
+    fixtures: HashMap<String, Fixture>,
+    // Where "downloaded" copies go, the shared download cache unless changed
+    download_path: PathBuf,
+}
+
+impl FakeDownloader {
+    pub fn from_dir(fixtures_path: &Path) -> BotResult<Self> {
+        let manifest = std::fs::read(fixtures_path.join("fixtures.json"))?;
+        let fixtures: Vec<Fixture> = match serde_json::from_slice(&manifest) {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::new(BotErrorKind::TypeError)),
+        };
+        let fixtures = fixtures
+            .into_iter()
+            .map(|mut fixture| {
+                fixture.file = fixtures_path.join(&fixture.file);
+                (fixture.url.clone(), fixture)
+            })
+            .collect();
+        Ok(FakeDownloader {
+            fixtures,
+            download_path: PathBuf::from(DOWNLOAD_PATH),
+        })
+    }
+
+    // Keeps tests from writing into the cache the bot uses
+    #[cfg(test)]
+    pub fn with_download_path(mut self, download_path: &Path) -> Self {
+        self.download_path = download_path.to_path_buf();
+        self
+    }
+
+    fn fixture(&self, url: &str) -> BotResult<&Fixture> {
+        match self.fixtures.get(url) {
+            Some(fixture) => Ok(fixture),
+            None => Err(BotError::new(BotErrorKind::DownloadError)),
+        }
+    }
+}
+
+#[async_trait]
+impl Downloader for FakeDownloader {
+    async fn fetch_info(&self, url: &str, _options: &DownloadOptions) -> BotResult<MediaInfo> {
+        Ok(self.fixture(url)?.info.clone())
+    }
+
+    async fn download_media(
+        &self,
+        url: &str,
+        info: &MediaInfo,
+        _options: &DownloadOptions,
+    ) -> BotResult<PathBuf> {
+        let fixture = self.fixture(url)?;
+        // Copy the fixture so callers are free to delete the file once they're done with it
+        let extension = fixture.file.extension().unwrap_or_default();
+        let file_path = self.download_path.join(&info.id).with_extension(extension);
+        tokio::fs::copy(&fixture.file, &file_path).await?;
+        Ok(file_path)
+    }
+}
@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    process::Timeouts,
+    types::{BotError, BotErrorKind, BotResult},
+};
+
+use super::{transcode, DownloadOptions, Downloader, MediaInfo, DOWNLOAD_PATH};
+
+const MEDIA_EXTENSIONS: [&str; 9] = [
+    "aac", "flac", "m4a", "mp3", "mp4", "oga", "ogg", "opus", "wav",
+];
+
+// Downloads plain media urls, such as "https://example.com/episode.mp3", without yt-dlp
+pub struct HttpDownloader {
+    client: Client,
+    timeouts: Timeouts,
+}
+
+impl HttpDownloader {
+    pub fn new(timeouts: Timeouts) -> Self {
+        HttpDownloader {
+            client: Client::new(),
+            timeouts,
+        }
+    }
+
+    // Returns true if the url points directly at a file with a known media extension
+    pub fn is_media_url(url: &str) -> bool {
+        Url::parse(url)
+            .ok()
+            .and_then(|url| Self::file_name(&url))
+            .and_then(|name| {
+                Path::new(&name)
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+            })
+            .map(|ext| MEDIA_EXTENSIONS.contains(&ext.as_str()))
+            .unwrap_or(false)
+    }
+
+    fn file_name(url: &Url) -> Option<String> {
+        url.path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+    }
+}
+
+#[async_trait]
+impl Downloader for HttpDownloader {
+    async fn fetch_info(&self, url: &str, _options: &DownloadOptions) -> BotResult<MediaInfo> {
+        let parsed_url = match Url::parse(url) {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::new(BotErrorKind::InvalidUrlError)),
+        };
+        let file_name = match Self::file_name(&parsed_url) {
+            Some(value) => value,
+            None => return Err(BotError::new(BotErrorKind::InvalidUrlError)),
+        };
+        // There's no video id to go by, so the url is hashed to get a stable filename
+        let id = hex::encode(&Sha256::digest(url.as_bytes())[..8]);
+        let title = Path::new(&file_name)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or(file_name);
+        Ok(MediaInfo {
+            id,
+            title,
+            channel: parsed_url.host_str().map(String::from),
+            ..Default::default()
+        })
+    }
+
+    async fn download_media(
+        &self,
+        url: &str,
+        info: &MediaInfo,
+        options: &DownloadOptions,
+    ) -> BotResult<PathBuf> {
+        let extension = Url::parse(url)
+            .ok()
+            .and_then(|url| Self::file_name(&url))
+            .and_then(|name| {
+                Path::new(&name)
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+            })
+            .unwrap_or_default();
+        let file_path = Path::new(DOWNLOAD_PATH).join(format!("{}.{}", info.id, extension));
+
+        let client = match &options.proxy {
+            Some(proxy) => Client::builder()
+                .proxy(reqwest::Proxy::all(proxy)?)
+                .build()?,
+            None => self.client.clone(),
+        };
+        let heartbeat = options.heartbeat.as_ref();
+        let download = async {
+            let response = client.get(url).send().await?;
+            if !response.status().is_success() {
+                return Err(BotError::new(BotErrorKind::DownloadError));
+            }
+            let mut file = File::create(&file_path).await?;
+            let mut stream = response.bytes_stream();
+            while let Some(chunk) = stream.next().await {
+                file.write_all(&chunk?).await?;
+                if let Some(heartbeat) = heartbeat {
+                    heartbeat.beat();
+                }
+            }
+            file.flush().await?;
+            Ok(())
+        };
+        match tokio::time::timeout(self.timeouts.download, download).await {
+            Ok(result) => result?,
+            Err(_) => return Err(BotError::new(BotErrorKind::TimeoutError)),
+        }
+
+        transcode(&file_path, options, self.timeouts.postprocess).await
+    }
+}
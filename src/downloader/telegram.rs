@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use teloxide::{net::Download, requests::Requester, Bot};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::{
+    process::Timeouts,
+    types::{BotError, BotErrorKind, BotResult},
+};
+
+use super::{transcode, DownloadOptions, Downloader, MediaInfo, DOWNLOAD_PATH};
+
+// Files sent to the bot are queued as "telegram:<file id>"
+pub const URL_PREFIX: &str = "telegram:";
+
+// Downloads audio, voice and video messages sent directly to the bot through the Bot API
+pub struct TelegramDownloader {
+    bot: Bot,
+    timeouts: Timeouts,
+}
+
+impl TelegramDownloader {
+    pub fn new(bot: Bot, timeouts: Timeouts) -> Self {
+        TelegramDownloader { bot, timeouts }
+    }
+
+    pub fn is_telegram_url(url: &str) -> bool {
+        url.starts_with(URL_PREFIX)
+    }
+
+    fn file_id(url: &str) -> BotResult<&str> {
+        match url.strip_prefix(URL_PREFIX) {
+            Some(file_id) if !file_id.is_empty() => Ok(file_id),
+            _ => Err(BotError::new(BotErrorKind::InvalidUrlError)),
+        }
+    }
+}
+
+#[async_trait]
+impl Downloader for TelegramDownloader {
+    async fn fetch_info(&self, url: &str, _options: &DownloadOptions) -> BotResult<MediaInfo> {
+        let file = self.bot.get_file(Self::file_id(url)?).await?;
+        // The title is whatever the user sent along with the file, it's filled in by the queue
+        Ok(MediaInfo {
+            id: file.meta.unique_id.clone(),
+            ..Default::default()
+        })
+    }
+
+    async fn download_media(
+        &self,
+        url: &str,
+        info: &MediaInfo,
+        options: &DownloadOptions,
+    ) -> BotResult<PathBuf> {
+        let file = self.bot.get_file(Self::file_id(url)?).await?;
+        // Telegram keeps the original extension, e.g. "voice/file_1.oga" or "music/file_2.mp3"
+        let extension = Path::new(&file.path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let file_path = Path::new(DOWNLOAD_PATH).join(format!("{}.{}", info.id, extension));
+
+        let download = async {
+            let mut destination = File::create(&file_path).await?;
+            self.bot.download_file(&file.path, &mut destination).await?;
+            destination.flush().await?;
+            Ok::<(), BotError>(())
+        };
+        match tokio::time::timeout(self.timeouts.download, download).await {
+            Ok(result) => result?,
+            Err(_) => return Err(BotError::new(BotErrorKind::TimeoutError)),
+        }
+        if let Some(heartbeat) = &options.heartbeat {
+            heartbeat.beat();
+        }
+
+        transcode(&file_path, options, self.timeouts.postprocess).await
+    }
+}
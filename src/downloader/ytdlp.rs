@@ -0,0 +1,356 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    bot::ConfigParameters,
+    process::{self, Timeouts},
+    types::{BotError, BotErrorKind, BotResult},
+};
+
+use super::{DownloadOptions, Downloader, MediaInfo, DOWNLOAD_PATH};
+
+// Shared path to the yt-dlp executable, so it can be swapped out while the bot is running
+#[derive(Clone)]
+pub struct YtDlpBinary {
+    path: Arc<RwLock<PathBuf>>,
+}
+
+impl YtDlpBinary {
+    pub fn new(path: PathBuf) -> Self {
+        YtDlpBinary {
+            path: Arc::new(RwLock::new(path)),
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.path.read().unwrap().clone()
+    }
+
+    // Returns the previous path
+    pub fn replace(&self, path: PathBuf) -> PathBuf {
+        std::mem::replace(&mut *self.path.write().unwrap(), path)
+    }
+}
+
+impl Default for YtDlpBinary {
+    // Whatever yt-dlp is found in $PATH
+    fn default() -> Self {
+        YtDlpBinary::new(PathBuf::from("yt-dlp"))
+    }
+}
+
+pub struct YtDlp {
+    binary: YtDlpBinary,
+    // Rate limiting arguments, the same for every download
+    throttle_args: Vec<String>,
+    timeouts: Timeouts,
+}
+
+impl YtDlp {
+    pub fn new(cfg: &ConfigParameters, binary: YtDlpBinary) -> Self {
+        let mut throttle_args = Vec::new();
+        if let Some(rate_limit) = &cfg.download_rate_limit {
+            throttle_args.push(String::from("--limit-rate"));
+            throttle_args.push(rate_limit.to_string());
+        }
+        if let Some(interval) = cfg.download_sleep_interval {
+            throttle_args.push(String::from("--sleep-interval"));
+            throttle_args.push(interval.to_string());
+            // yt-dlp only accepts a max interval alongside the min interval
+            if let Some(max_interval) = cfg.download_max_sleep_interval {
+                throttle_args.push(String::from("--max-sleep-interval"));
+                throttle_args.push(max_interval.to_string());
+            }
+        }
+        if let Some(interval) = cfg.download_sleep_requests {
+            throttle_args.push(String::from("--sleep-requests"));
+            throttle_args.push(interval.to_string());
+        }
+        YtDlp {
+            binary,
+            throttle_args,
+            timeouts: Timeouts::new(cfg),
+        }
+    }
+
+    async fn run_yt_dlp(
+        &self,
+        url: &str,
+        custom_args: Vec<&str>,
+        options: &DownloadOptions,
+        timeout: Duration,
+    ) -> BotResult<Output> {
+        let yt_dlp_path = self.binary.path();
+        let download_path = Path::new(DOWNLOAD_PATH);
+        let default_args = vec![
+            "--quiet",
+            "--no-warnings",
+            "--add-metadata",
+            // TODO: Figure out why this works in local container and fails in fly.io container
+            //"--embed-thumbnail",
+            "--output",
+            "%(id)s.%(ext)s",
+        ];
+        let mut command = Command::new(yt_dlp_path);
+        command.current_dir(download_path);
+        for arg in default_args.into_iter() {
+            command.arg(arg);
+        }
+        command.args(format_args(options));
+        for arg in custom_args.into_iter() {
+            command.arg(arg);
+        }
+        command.args(&self.throttle_args);
+        if let Some(proxy) = &options.proxy {
+            command.arg("--proxy").arg(proxy);
+        }
+        if let Some(cookies_path) = &options.cookies_path {
+            command.arg("--cookies").arg(cookies_path);
+        }
+        // Make sure the source url is the last argument
+        command.arg(url);
+
+        let output = process::run(command, timeout, options.heartbeat.as_ref()).await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(BotError::new(classify_error(&stderr)));
+        }
+        Ok(output)
+    }
+}
+
+// Audio only by default. In video mode, the best MP4 no taller than the limit, preferring
+// H.264/AAC which plays everywhere.
+fn format_args(options: &DownloadOptions) -> Vec<String> {
+    match options.video_max_height {
+        Some(max_height) => vec![
+            String::from("--format"),
+            format!(
+                "bestvideo[height<={0}]+bestaudio/best[height<={0}]/best",
+                max_height
+            ),
+            String::from("--format-sort"),
+            String::from("vcodec:h264,acodec:aac"),
+            String::from("--merge-output-format"),
+            String::from("mp4"),
+            // The single file fallbacks aren't merged, e.g. "best" may be a webm
+            String::from("--remux-video"),
+            String::from("mp4"),
+        ],
+        None => vec![
+            String::from("--format"),
+            String::from("bestaudio"),
+            String::from("--extract-audio"),
+            String::from("--audio-format"),
+            String::from("m4a"),
+        ],
+    }
+}
+
+// Picks the most specific error from yt-dlp's stderr, e.g. "ERROR: [youtube] abc: Private video"
+fn classify_error(stderr: &str) -> BotErrorKind {
+    let stderr = stderr.to_lowercase();
+    let has = |patterns: &[&str]| patterns.iter().any(|pattern| stderr.contains(pattern));
+    // Order matters, e.g. copyright claims also say "video unavailable"
+    if has(&["private video"]) {
+        BotErrorKind::PrivateVideoError
+    } else if has(&["members-only", "members only", "join this channel"]) {
+        BotErrorKind::MembersOnlyError
+    } else if has(&[
+        "confirm your age",
+        "age-restricted",
+        "inappropriate for some users",
+    ]) {
+        BotErrorKind::AgeRestrictedError
+    } else if has(&["copyright"]) {
+        BotErrorKind::CopyrightError
+    } else if has(&[
+        // e.g. "The uploader has not made this video available in your country"
+        "available in your country",
+        "geo restrict",
+        "geo-restrict",
+    ]) {
+        BotErrorKind::GeoBlockedError
+    } else if has(&[
+        "http error 429",
+        "too many requests",
+        "not a bot",
+        "rate-limit",
+    ]) {
+        BotErrorKind::RateLimitedError
+    } else if has(&["unsupported url"]) {
+        BotErrorKind::UnsupportedUrlError
+    } else if has(&[
+        "video unavailable",
+        "has been removed",
+        "has been terminated",
+        "does not exist",
+        "http error 404",
+    ]) {
+        BotErrorKind::VideoRemovedError
+    } else {
+        BotErrorKind::DownloadError
+    }
+}
+
+#[async_trait]
+impl Downloader for YtDlp {
+    async fn fetch_info(&self, url: &str, options: &DownloadOptions) -> BotResult<MediaInfo> {
+        // Don't download yet, only get the details of the video to use later
+        // Upcoming streams have no formats yet, but their details are still needed
+        let dry_run_args = vec!["--dump-json", "--no-playlist", "--ignore-no-formats-error"];
+        let dry_run_output = self
+            .run_yt_dlp(url, dry_run_args, options, self.timeouts.metadata)
+            .await?;
+        match serde_json::from_slice::<MediaInfo>(&dry_run_output.stdout) {
+            Ok(info) => Ok(info),
+            Err(_) => Err(BotError::new(BotErrorKind::DownloadError)),
+        }
+    }
+
+    async fn download_media(
+        &self,
+        url: &str,
+        _info: &MediaInfo,
+        options: &DownloadOptions,
+    ) -> BotResult<PathBuf> {
+        // Download the video using the video ID as the filename
+        let download_args = vec![
+            "--no-simulate",
+            "--no-playlist",
+            "--verbose",
+            // Progress lines keep the watchdog from flagging long downloads
+            "--progress",
+            "--newline",
+            "--print",
+            "after_move:filepath",
+        ];
+        let download_output = self
+            .run_yt_dlp(url, download_args, options, self.timeouts.download)
+            .await?;
+        // The string from stdout has a newline at the end we don't want
+        let file_path_string = String::from_utf8(download_output.stdout)?.replace('\n', "");
+        Ok(PathBuf::from(file_path_string))
+    }
+
+    async fn download_subtitles(
+        &self,
+        url: &str,
+        info: &MediaInfo,
+        language: &str,
+        options: &DownloadOptions,
+    ) -> BotResult<Option<PathBuf>> {
+        // Prefer subtitles written by the uploader, otherwise fall back to auto-generated ones
+        let subtitle_args = vec![
+            "--skip-download",
+            "--no-playlist",
+            "--write-subs",
+            "--write-auto-subs",
+            "--sub-langs",
+            language,
+            "--convert-subs",
+            "srt",
+        ];
+        self.run_yt_dlp(url, subtitle_args, options, self.timeouts.metadata)
+            .await?;
+        // Files are named "<id>.<language>.srt"
+        let subtitles_path = Path::new(DOWNLOAD_PATH).join(format!("{}.{}.srt", info.id, language));
+        match tokio::fs::metadata(&subtitles_path).await {
+            Ok(_) => Ok(Some(subtitles_path)),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Lines as yt-dlp prints them, with the kind they should map to and whether it's retried
+    const CASES: [(&str, &str, bool); 10] = [
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video",
+            "PrivateVideoError",
+            false,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Join this channel to get access to members-only content like this video, and other exclusive perks.",
+            "MembersOnlyError",
+            false,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm your age. This video may be inappropriate for some users.",
+            "AgeRestrictedError",
+            false,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video contains content from SME, who has blocked it on copyright grounds",
+            "CopyrightError",
+            false,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video has been removed by the uploader",
+            "VideoRemovedError",
+            false,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: The uploader has not made this video available in your country\nYou might want to use a VPN or a proxy server (with --proxy) to workaround.",
+            "GeoBlockedError",
+            false,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Unable to download API page: HTTP Error 429: Too Many Requests (caused by <HTTPError 429: Too Many Requests>)",
+            "RateLimitedError",
+            true,
+        ),
+        (
+            "ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm you’re not a bot. This helps protect our community.",
+            "RateLimitedError",
+            true,
+        ),
+        (
+            "ERROR: Unsupported URL: https://example.com/page",
+            "UnsupportedUrlError",
+            false,
+        ),
+        (
+            "ERROR: [download] Got error: ('Connection broken: IncompleteRead(0 bytes read)', IncompleteRead(0 bytes read))",
+            "DownloadError",
+            true,
+        ),
+    ];
+
+    #[test]
+    fn classifies_errors() {
+        for (stderr, kind, _) in CASES {
+            assert_eq!(format!("{:?}", classify_error(stderr)), kind, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn retries_only_temporary_errors() {
+        for (stderr, _, retryable) in CASES {
+            assert_eq!(
+                classify_error(stderr).is_retryable(),
+                retryable,
+                "{}",
+                stderr
+            );
+        }
+    }
+
+    #[test]
+    fn warnings_before_the_error_are_ignored() {
+        let stderr = "WARNING: [youtube] Falling back to generic n function search\nERROR: [youtube] dQw4w9WgXcQ: Private video";
+        assert!(matches!(
+            classify_error(stderr),
+            BotErrorKind::PrivateVideoError
+        ));
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{TimeZone, Utc};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    sync::{Mutex as AsyncMutex, OwnedMutexGuard},
+};
+
+use crate::{
+    bot::ConfigParameters,
+    crypto,
+    database::Database,
+    downloader::{DownloadedMedia, MediaInfo},
+    queue::unix_time,
+    types::{BotError, BotErrorKind, BotResult},
+    uploader,
+};
+
+// Served by the webserver under /feeds/<token>/, see main::run_webserver
+pub const FEEDS_ROUTE: &str = "feeds";
+const FEED_FILE_NAME: &str = "feed.xml";
+
+// 16 random bytes, the token is all that keeps a feed private
+const TOKEN_LENGTH: usize = 16;
+
+// One lock per user for the whole process, so concurrent rewrites of a user's feed.xml run one
+// after another and don't lose each other's episodes
+static LOCKS: Lazy<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// An episode kept on disk for a user's feed
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Episode {
+    pub id: String,
+    pub title: String,
+    pub file_name: String,
+    // In bytes
+    pub size: u64,
+    // In seconds
+    #[serde(default)]
+    pub duration: Option<f64>,
+    // Unix time it was added
+    pub published: u64,
+    // The video it came from
+    #[serde(default)]
+    pub link: Option<String>,
+    #[serde(default)]
+    pub artwork: Option<String>,
+    #[serde(default)]
+    pub chapters: Vec<EpisodeChapter>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EpisodeChapter {
+    // In seconds
+    pub start_time: f64,
+    pub title: String,
+}
+
+// Private podcast feeds for users who don't use Pocket Casts. Each feed lives in its own
+// folder named after a secret token, next to the audio files it links to.
+#[derive(Clone)]
+pub struct Feed {
+    database: Database,
+    path: PathBuf,
+    // e.g. "https://bot.example.com", feeds are off without it
+    base_url: Option<String>,
+    max_episodes: usize,
+}
+
+impl Feed {
+    pub fn new(database: Database, cfg: &ConfigParameters) -> Self {
+        Feed {
+            database,
+            path: cfg.feed_path.clone(),
+            base_url: cfg
+                .feed_base_url
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_string()),
+            max_episodes: cfg.feed_max_episodes.max(1),
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.base_url.is_some()
+    }
+
+    // Url to subscribe to, the feed is created the first time
+    pub async fn url(&mut self, user_id: &str) -> BotResult<String> {
+        let _guard = self.lock(user_id).await;
+        let token = match self.get_token(user_id).await? {
+            Some(value) => value,
+            None => self.create_token(user_id).await?,
+        };
+        let episodes = self.get_episodes(user_id).await?;
+        self.write(&token, &episodes).await?;
+        self.feed_url(&token)
+    }
+
+    // Gives the feed a new url, the old one stops working. Episodes are kept.
+    pub async fn reset(&mut self, user_id: &str) -> BotResult<String> {
+        let _guard = self.lock(user_id).await;
+        let old_token = self.get_token(user_id).await?;
+        let token = self.create_token(user_id).await?;
+        if let Some(old_token) = old_token {
+            let old_path = self.path.join(old_token);
+            if fs::metadata(&old_path).await.is_ok() {
+                fs::rename(&old_path, self.path.join(&token)).await?;
+            }
+        }
+        let episodes = self.get_episodes(user_id).await?;
+        self.write(&token, &episodes).await?;
+        self.feed_url(&token)
+    }
+
+    // Copies the downloaded file into the user's feed. The oldest episodes are deleted once
+    // there are more than `max_episodes`.
+    pub async fn add_episode(
+        &mut self,
+        user_id: &str,
+        media: &DownloadedMedia,
+        info: &MediaInfo,
+        link: &str,
+    ) -> BotResult<Episode> {
+        if !self.is_available() {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        let _guard = self.lock(user_id).await;
+        let token = match self.get_token(user_id).await? {
+            Some(value) => value,
+            None => self.create_token(user_id).await?,
+        };
+        let feed_path = self.path.join(&token);
+        fs::create_dir_all(&feed_path).await?;
+
+        let id = crypto::random_token(8);
+        let extension = media
+            .file_path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("m4a"));
+        let file_name = format!("{}.{}", id, extension);
+        // The download stays in the cache, other destinations may still need it
+        fs::copy(&media.file_path, feed_path.join(&file_name)).await?;
+
+        let episode = Episode {
+            id,
+            title: media.title.clone(),
+            size: fs::metadata(feed_path.join(&file_name)).await?.len(),
+            file_name,
+            duration: info.duration,
+            published: unix_time(),
+            link: link.starts_with("http").then(|| link.to_string()),
+            artwork: info.thumbnail.clone(),
+            chapters: info
+                .chapters
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|chapter| {
+                    Some(EpisodeChapter {
+                        start_time: chapter.start_time,
+                        title: chapter.title.clone()?,
+                    })
+                })
+                .collect(),
+        };
+        let mut episodes = self.get_episodes(user_id).await?;
+        episodes.insert(0, episode.clone());
+        if episodes.len() > self.max_episodes {
+            for old_episode in episodes.split_off(self.max_episodes) {
+                fs::remove_file(feed_path.join(&old_episode.file_name))
+                    .await
+                    .ok();
+            }
+        }
+        self.set_episodes(user_id, &episodes).await?;
+        self.write(&token, &episodes).await?;
+        Ok(episode)
+    }
+
+    async fn lock(&self, user_id: &str) -> OwnedMutexGuard<()> {
+        let lock = LOCKS
+            .lock()
+            .unwrap()
+            .entry(user_id.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
+    }
+
+    async fn get_token(&mut self, user_id: &str) -> BotResult<Option<String>> {
+        self.database
+            .get_setting(user_id.to_string(), "feed_token")
+            .await
+    }
+
+    async fn create_token(&mut self, user_id: &str) -> BotResult<String> {
+        let token = crypto::random_token(TOKEN_LENGTH);
+        self.database
+            .set_setting(user_id.to_string(), "feed_token", token.clone())
+            .await?;
+        Ok(token)
+    }
+
+    async fn get_episodes(&mut self, user_id: &str) -> BotResult<Vec<Episode>> {
+        match self
+            .database
+            .get_setting(user_id.to_string(), "feed_episodes")
+            .await?
+        {
+            Some(value) => match serde_json::from_str(&value) {
+                Ok(episodes) => Ok(episodes),
+                Err(_) => Err(BotError::new(BotErrorKind::TypeError)),
+            },
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn set_episodes(&mut self, user_id: &str, episodes: &[Episode]) -> BotResult<()> {
+        let value = match serde_json::to_string(episodes) {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::new(BotErrorKind::TypeError)),
+        };
+        self.database
+            .set_setting(user_id.to_string(), "feed_episodes", value)
+            .await
+    }
+
+    fn feed_url(&self, token: &str) -> BotResult<String> {
+        match &self.base_url {
+            Some(base_url) => Ok(format!(
+                "{}/{}/{}/{}",
+                base_url, FEEDS_ROUTE, token, FEED_FILE_NAME
+            )),
+            None => Err(BotError::new(BotErrorKind::InvalidSettingError)),
+        }
+    }
+
+    // The feed is a static file, rewritten whenever an episode is added
+    async fn write(&self, token: &str, episodes: &[Episode]) -> BotResult<()> {
+        let base_url = match &self.base_url {
+            Some(value) => value,
+            None => return Err(BotError::new(BotErrorKind::InvalidSettingError)),
+        };
+        let feed_path = self.path.join(token);
+        fs::create_dir_all(&feed_path).await?;
+        let episodes_url = format!("{}/{}/{}", base_url, FEEDS_ROUTE, token);
+        let contents = render(&episodes_url, episodes);
+        // Written next to the feed first, so podcast apps never see half a file
+        let temporary_path = feed_path.join(format!("{}.tmp", FEED_FILE_NAME));
+        fs::write(&temporary_path, contents).await?;
+        fs::rename(&temporary_path, feed_path.join(FEED_FILE_NAME)).await?;
+        Ok(())
+    }
+}
+
+fn render(episodes_url: &str, episodes: &[Episode]) -> String {
+    let mut items = String::new();
+    for episode in episodes.iter() {
+        items.push_str(&render_episode(episodes_url, episode));
+    }
+    // The newest episode's artwork stands in for the podcast's
+    let artwork = episodes
+        .iter()
+        .find_map(|episode| episode.artwork.as_deref())
+        .map(|url| format!("    <itunes:image href=\"{}\"/>\n", escape(url)))
+        .unwrap_or_default();
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd" xmlns:psc="http://podlove.org/simple-chapters">
+  <channel>
+    <title>Private feed</title>
+    <description>Videos sent to the bot</description>
+    <link>{}/{}</link>
+    <itunes:block>Yes</itunes:block>
+{}{}  </channel>
+</rss>
+"#,
+        escape(episodes_url),
+        FEED_FILE_NAME,
+        artwork,
+        items
+    )
+}
+
+fn render_episode(episodes_url: &str, episode: &Episode) -> String {
+    let mut item = String::from("    <item>\n");
+    item.push_str(&format!(
+        "      <title>{}</title>\n",
+        escape(&episode.title)
+    ));
+    item.push_str(&format!(
+        "      <guid isPermaLink=\"false\">{}</guid>\n",
+        episode.id
+    ));
+    item.push_str(&format!(
+        "      <pubDate>{}</pubDate>\n",
+        format_date(episode.published)
+    ));
+    item.push_str(&format!(
+        "      <enclosure url=\"{}/{}\" length=\"{}\" type=\"{}\"/>\n",
+        escape(episodes_url),
+        escape(&episode.file_name),
+        episode.size,
+        uploader::content_type(Path::new(&episode.file_name))
+    ));
+    if let Some(link) = &episode.link {
+        item.push_str(&format!("      <link>{}</link>\n", escape(link)));
+    }
+    if let Some(duration) = episode.duration {
+        item.push_str(&format!(
+            "      <itunes:duration>{}</itunes:duration>\n",
+            duration.round() as u64
+        ));
+    }
+    if let Some(artwork) = &episode.artwork {
+        item.push_str(&format!(
+            "      <itunes:image href=\"{}\"/>\n",
+            escape(artwork)
+        ));
+    }
+    if !episode.chapters.is_empty() {
+        item.push_str("      <psc:chapters version=\"1.2\">\n");
+        for chapter in episode.chapters.iter() {
+            item.push_str(&format!(
+                "        <psc:chapter start=\"{}\" title=\"{}\"/>\n",
+                format_time(chapter.start_time),
+                escape(&chapter.title)
+            ));
+        }
+        item.push_str("      </psc:chapters>\n");
+    }
+    item.push_str("    </item>\n");
+    item
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// RSS wants RFC 2822 dates, e.g. "Sat, 04 Mar 2023 12:34:56 +0000"
+fn format_date(unix_time: u64) -> String {
+    Utc.timestamp_opt(unix_time as i64, 0)
+        .single()
+        .map(|date| date.to_rfc2822())
+        .unwrap_or_default()
+}
+
+// Simple chapters want "01:02:03.456"
+fn format_time(seconds: f64) -> String {
+    let milliseconds = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        milliseconds / 3_600_000,
+        (milliseconds % 3_600_000) / 60_000,
+        (milliseconds % 60_000) / 1000,
+        milliseconds % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn episode() -> Episode {
+        Episode {
+            id: String::from("abc123"),
+            title: String::from("Q&A: <Live> \"special\""),
+            file_name: String::from("a&b.m4a"),
+            size: 1024,
+            duration: Some(61.6),
+            published: 0,
+            link: Some(String::from("https://youtu.be/video?t=1&list=2")),
+            artwork: None,
+            chapters: vec![EpisodeChapter {
+                start_time: 3723.456,
+                title: String::from("Tom's chapter"),
+            }],
+        }
+    }
+
+    #[test]
+    fn escapes_titles_and_urls() {
+        let feed = render("https://bot.example.com/feeds/token?x=1&y=2", &[episode()]);
+        assert!(feed.contains("<title>Q&amp;A: &lt;Live&gt; &quot;special&quot;</title>"));
+        assert!(feed.contains(
+            "<enclosure url=\"https://bot.example.com/feeds/token?x=1&amp;y=2/a&amp;b.m4a\" length=\"1024\""
+        ));
+        assert!(feed.contains("<link>https://youtu.be/video?t=1&amp;list=2</link>"));
+        assert!(feed.contains("title=\"Tom&apos;s chapter\""));
+        assert!(!feed.contains("Q&A"));
+    }
+
+    #[test]
+    fn renders_episode_details() {
+        let feed = render("https://bot.example.com/feeds/token", &[episode()]);
+        assert!(feed.contains("<guid isPermaLink=\"false\">abc123</guid>"));
+        assert!(feed.contains("<pubDate>Thu, 01 Jan 1970 00:00:00 +0000</pubDate>"));
+        assert!(feed.contains("<itunes:duration>62</itunes:duration>"));
+        assert!(feed.contains("<psc:chapter start=\"01:02:03.456\""));
+    }
+
+    #[test]
+    fn formats_chapter_times() {
+        assert_eq!(format_time(0.0), "00:00:00.000");
+        assert_eq!(format_time(61.5), "00:01:01.500");
+        assert_eq!(format_time(-3.0), "00:00:00.000");
+    }
+}
@@ -0,0 +1,191 @@
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::uploader::{self, PocketCastsFile, UploadedFile};
+
+// Files shown per page of /files
+const PAGE_SIZE: usize = 5;
+
+// Pocket Casts file colours, in the app's order. 0 means no colour.
+pub const COLOURS: [&str; 8] = [
+    "Red", "Orange", "Yellow", "Green", "Blue", "Indigo", "Purple", "Pink",
+];
+
+// Buttons in the file browser, stored in the callback data as e.g. "files:show:<uuid>".
+// Telegram limits callback data to 64 bytes, which leaves enough room for a uuid.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileAction {
+    Page(usize),
+    Show(String),
+    Rename(String),
+    ChooseColour(String),
+    SetColour(String, u8),
+    // Protected files are never deleted by housekeeping
+    Protect(String),
+    Unprotect(String),
+    Delete(String),
+}
+
+impl FileAction {
+    pub fn parse(data: &str) -> Option<Self> {
+        let mut parts = data.split(':');
+        if parts.next() != Some("files") {
+            return None;
+        }
+        let action = match (parts.next()?, parts.next()) {
+            ("page", Some(page)) => FileAction::Page(page.parse().ok()?),
+            ("show", Some(uuid)) => FileAction::Show(uuid.to_string()),
+            ("rename", Some(uuid)) => FileAction::Rename(uuid.to_string()),
+            ("colour", Some(uuid)) => FileAction::ChooseColour(uuid.to_string()),
+            ("setcolour", Some(uuid)) => {
+                FileAction::SetColour(uuid.to_string(), parts.next()?.parse().ok()?)
+            }
+            ("protect", Some(uuid)) => FileAction::Protect(uuid.to_string()),
+            ("unprotect", Some(uuid)) => FileAction::Unprotect(uuid.to_string()),
+            ("delete", Some(uuid)) => FileAction::Delete(uuid.to_string()),
+            _ => return None,
+        };
+        Some(action)
+    }
+
+    pub fn encode(&self) -> String {
+        match self {
+            FileAction::Page(page) => format!("files:page:{}", page),
+            FileAction::Show(uuid) => format!("files:show:{}", uuid),
+            FileAction::Rename(uuid) => format!("files:rename:{}", uuid),
+            FileAction::ChooseColour(uuid) => format!("files:colour:{}", uuid),
+            FileAction::SetColour(uuid, colour) => format!("files:setcolour:{}:{}", uuid, colour),
+            FileAction::Protect(uuid) => format!("files:protect:{}", uuid),
+            FileAction::Unprotect(uuid) => format!("files:unprotect:{}", uuid),
+            FileAction::Delete(uuid) => format!("files:delete:{}", uuid),
+        }
+    }
+
+    // The file the action is about, paging isn't about any one file
+    pub fn uuid(&self) -> Option<&str> {
+        match self {
+            FileAction::Page(_) => None,
+            FileAction::Show(uuid)
+            | FileAction::Rename(uuid)
+            | FileAction::ChooseColour(uuid)
+            | FileAction::SetColour(uuid, _)
+            | FileAction::Protect(uuid)
+            | FileAction::Unprotect(uuid)
+            | FileAction::Delete(uuid) => Some(uuid),
+        }
+    }
+
+    fn button(&self, text: impl Into<String>) -> InlineKeyboardButton {
+        InlineKeyboardButton::callback(text, self.encode())
+    }
+}
+
+// One page of the user's files, with a button for each file and buttons to move between pages
+pub fn page(files: &[PocketCastsFile], page: usize) -> (String, InlineKeyboardMarkup) {
+    let pages = files.len().saturating_sub(1) / PAGE_SIZE + 1;
+    let page = page.min(pages - 1);
+    let start = page * PAGE_SIZE;
+    let page_files = files.iter().skip(start).take(PAGE_SIZE);
+
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = page_files
+        .map(|file| vec![FileAction::Show(file.uuid.clone()).button(&file.title)])
+        .collect();
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(FileAction::Page(page - 1).button("« Previous"));
+    }
+    if page + 1 < pages {
+        navigation.push(FileAction::Page(page + 1).button("Next »"));
+    }
+    if !navigation.is_empty() {
+        rows.push(navigation);
+    }
+    let text = format!(
+        "Your files ({}), page {} of {}. Pick one to rename, colour or delete it.",
+        files.len(),
+        page + 1,
+        pages
+    );
+    (text, InlineKeyboardMarkup::new(rows))
+}
+
+pub fn details(file: &PocketCastsFile, protected: bool) -> (String, InlineKeyboardMarkup) {
+    let mut lines = vec![file.title.clone()];
+    if let Some(duration) = file.duration {
+        let duration = duration.round() as u64;
+        lines.push(format!(
+            "Duration: {}:{:02}:{:02}",
+            duration / 3600,
+            (duration % 3600) / 60,
+            duration % 60
+        ));
+    }
+    if let Some(size) = file.size {
+        lines.push(format!("Size: {}", uploader::format_size(size)));
+    }
+    if let Some(colour) = colour_name(file.colour) {
+        lines.push(format!("Colour: {}", colour));
+    }
+    let protect_button = if protected {
+        lines.push(String::from("Protected from housekeeping"));
+        FileAction::Unprotect(file.uuid.clone()).button("Unprotect")
+    } else {
+        FileAction::Protect(file.uuid.clone()).button("Protect")
+    };
+    let keyboard = InlineKeyboardMarkup::new(vec![
+        vec![
+            FileAction::Rename(file.uuid.clone()).button("Rename"),
+            FileAction::ChooseColour(file.uuid.clone()).button("Colour"),
+            FileAction::Delete(file.uuid.clone()).button("Delete"),
+        ],
+        vec![
+            protect_button,
+            FileAction::Page(0).button("« Back to files"),
+        ],
+    ]);
+    (lines.join("\n"), keyboard)
+}
+
+pub fn colour_keyboard(uuid: &str) -> InlineKeyboardMarkup {
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = COLOURS
+        .chunks(4)
+        .enumerate()
+        .map(|(row, names)| {
+            names
+                .iter()
+                .enumerate()
+                .map(|(column, name)| {
+                    let colour = (row * 4 + column + 1) as u8;
+                    FileAction::SetColour(uuid.to_string(), colour).button(*name)
+                })
+                .collect()
+        })
+        .collect();
+    rows.push(vec![
+        FileAction::SetColour(uuid.to_string(), 0).button("No colour"),
+        FileAction::Show(uuid.to_string()).button("« Back"),
+    ]);
+    InlineKeyboardMarkup::new(rows)
+}
+
+// Attached to "Done!" so an accidental upload can be undone right away
+pub fn delete_buttons(files: &[UploadedFile]) -> Vec<Vec<InlineKeyboardButton>> {
+    files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| {
+            let text = if files.len() == 1 {
+                String::from("Delete")
+            } else {
+                format!("Delete part {}", index + 1)
+            };
+            vec![FileAction::Delete(file.uuid.clone()).button(text)]
+        })
+        .collect()
+}
+
+fn colour_name(colour: u8) -> Option<&'static str> {
+    match colour {
+        0 => None,
+        value => COLOURS.get(value as usize - 1).copied(),
+    }
+}
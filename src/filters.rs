@@ -1,30 +1,67 @@
-use teloxide::types::Message;
+use teloxide::types::{CallbackQuery, FileMeta, Message};
 
-use crate::bot::ConfigParameters;
+use crate::{bot::ConfigParameters, queue::Queue};
 
 // Returns true if incoming message is from a Trusted or Admin user
 pub async fn is_authorized(cfg: ConfigParameters, msg: Message) -> bool {
     msg.from()
         .map(|user| {
-            cfg.trusted_user_ids.iter().any(|&i| i == user.id)
-                || cfg.admin_user_ids.iter().any(|&i| i == user.id)
+            cfg.trusted_user_ids.contains(&user.id) || cfg.admin_user_ids.contains(&user.id)
         })
         .unwrap_or(false)
 }
 
+// Returns true if a button was pressed by a Trusted or Admin user
+pub async fn is_authorized_callback(cfg: ConfigParameters, query: CallbackQuery) -> bool {
+    cfg.trusted_user_ids.contains(&query.from.id) || cfg.admin_user_ids.contains(&query.from.id)
+}
+
 // Returns true if incoming message is from an Admin user
 pub async fn is_admin(cfg: ConfigParameters, msg: Message) -> bool {
     msg.from()
-        .map(|user| cfg.admin_user_ids.iter().any(|&i| i == user.id))
+        .map(|user| cfg.admin_user_ids.contains(&user.id))
         .unwrap_or(false)
 }
 
-// Returns true if incoming message starts with "http"
+// Returns true if incoming message starts with "http", optionally after "video", "audio" or
+// a profile name
 pub async fn is_link(msg: Message) -> bool {
     let incoming_text = msg.text().unwrap_or_default();
-    if incoming_text.starts_with("http") {
-        return true;
-    } else {
-        return false;
+    Queue::parse_request(incoming_text).url.starts_with("http")
+}
+
+// Returns true if incoming message has an audio, voice or video file attached
+pub async fn is_media(msg: Message) -> bool {
+    media_file(&msg).is_some()
+}
+
+// Returns the attached media file along with its best guess at a title
+pub fn media_file(msg: &Message) -> Option<(&FileMeta, Option<String>)> {
+    if let Some(audio) = msg.audio() {
+        let title = match (&audio.performer, &audio.title) {
+            (Some(performer), Some(title)) => Some(format!("{} - {}", performer, title)),
+            (None, Some(title)) => Some(title.to_string()),
+            _ => audio.file_name.clone(),
+        };
+        return Some((&audio.file, title));
+    }
+    if let Some(voice) = msg.voice() {
+        return Some((&voice.file, None));
+    }
+    if let Some(video) = msg.video() {
+        return Some((&video.file, video.file_name.clone()));
     }
+    if let Some(video_note) = msg.video_note() {
+        return Some((&video_note.file, None));
+    }
+    // Files sent "as a file" instead of as audio or video
+    msg.document()
+        .filter(|document| {
+            document
+                .mime_type
+                .as_ref()
+                .map(|mime| mime.type_() == "audio" || mime.type_() == "video")
+                .unwrap_or(false)
+        })
+        .map(|document| (&document.file, document.file_name.clone()))
 }
@@ -1,13 +1,29 @@
 use std::sync::Arc;
 
-use teloxide::{requests::Requester, types::Message, utils::command::BotCommands};
+use teloxide::{
+    dispatching::dialogue::InMemStorage,
+    net::Download,
+    payloads::{
+        AnswerCallbackQuerySetters, EditMessageReplyMarkupSetters, EditMessageTextSetters,
+        SendMessageSetters,
+    },
+    requests::Requester,
+    types::{CallbackQuery, Message},
+    utils::command::BotCommands,
+};
 use tokio::sync::RwLock;
 
 use crate::{
     bot::{BotData, CommandState, Commands},
-    queue::Queue,
-    types::BotDialogue,
-    user::User,
+    destination::{self, S3Config, WebDavConfig},
+    files::{self, FileAction},
+    filters, housekeeping,
+    queue::{self, Queue},
+    subtitles::SubtitlePreference,
+    titles::{self, TitleFilter},
+    types::{BotDialogue, BotError},
+    uploader::{self, UpNextPosition},
+    user::{self, User},
 };
 
 pub async fn unrecognized(bot: teloxide::Bot, msg: Message) -> Result<(), teloxide::RequestError> {
@@ -16,7 +32,7 @@ pub async fn unrecognized(bot: teloxide::Bot, msg: Message) -> Result<(), teloxi
 }
 
 pub async fn start(bot: teloxide::Bot, msg: Message) -> Result<(), teloxide::RequestError> {
-    bot.send_message(msg.chat.id, "This bot sends Youtube videos as audio podcasts to your personal Pocket Casts files section.\n\nTo get user id: /id\n\nTo start: /auth")
+    bot.send_message(msg.chat.id, "This bot sends Youtube videos as audio podcasts to your personal Pocket Casts files section. Audio, voice and video files sent to the bot are uploaded too.\n\nTo get user id: /id\n\nTo start: /auth")
         .await?;
     Ok(())
 }
@@ -39,6 +55,7 @@ pub async fn auth_clear(
     bot: teloxide::Bot,
     dialogue: BotDialogue,
     msg: Message,
+    profile: String,
     bot_data: Arc<RwLock<BotData>>,
 ) -> Result<(), teloxide::RequestError> {
     let mut db_client = bot_data.read().await.db_client.clone();
@@ -51,9 +68,16 @@ pub async fn auth_clear(
         }
     };
 
-    let output = match User::delete_token(&mut db_client, user_id.to_string()).await {
+    let result = match User::parse_profile(&profile) {
+        Ok(profile) => User::delete_token(&mut db_client, user_id.to_string(), &profile).await,
+        Err(error) => Err(error),
+    };
+    let output = match result {
         Ok(_) => String::from("Token removed successfully."),
         Err(error) => match error.kind {
+            crate::types::BotErrorKind::InvalidSettingError => {
+                String::from("Please send a valid profile name, e.g. /clear work")
+            }
             crate::types::BotErrorKind::RedisError => {
                 String::from("Unable to remove token. Please try again.")
             }
@@ -65,15 +89,35 @@ pub async fn auth_clear(
     Ok(())
 }
 
+// "/auth" sets the default profile's token, "/auth work" the token of a profile called work
 pub async fn auth_initiate(
     bot: teloxide::Bot,
     dialogue: BotDialogue,
     msg: Message,
+    profile: String,
 ) -> Result<(), teloxide::RequestError> {
-    bot.send_message(msg.chat.id, "What is your auth token? /cancel to stop")
-        .await?;
+    let profile = match User::parse_profile(&profile) {
+        Ok(value) => value,
+        Err(_) => {
+            bot.send_message(
+                msg.chat.id,
+                "Profile names can only contain letters, numbers, - and _, e.g. /auth work",
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let output = if profile == user::DEFAULT_PROFILE {
+        String::from("What is your auth token? /cancel to stop")
+    } else {
+        format!(
+            "What is your auth token for the {} profile? /cancel to stop",
+            profile
+        )
+    };
+    bot.send_message(msg.chat.id, output).await?;
     dialogue
-        .update(CommandState::ReceiveAuthToken)
+        .update(CommandState::ReceiveAuthToken { profile })
         .await
         .unwrap();
     Ok(())
@@ -83,6 +127,7 @@ pub async fn receive_token(
     bot: teloxide::Bot,
     dialogue: BotDialogue,
     msg: Message,
+    profile: String,
     bot_data: Arc<RwLock<BotData>>,
 ) -> Result<(), teloxide::RequestError> {
     let user_id = match msg.from() {
@@ -96,17 +141,51 @@ pub async fn receive_token(
 
     let token = msg.text().unwrap_or_default().to_string();
     let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
 
-    let msg_response = match User::set_token(&mut db_client, user_id.to_string(), token).await {
-        Ok(_) => {
+    let msg_response = match User::set_token(
+        &mut db_client,
+        &pocket_casts,
+        user_id.to_string(),
+        &profile,
+        token,
+    )
+    .await
+    {
+        Ok(claims) => {
             dialogue.exit().await.unwrap();
-            String::from("Token set. Start sending me some youtube videos.")
+            let account = match (claims.email, claims.sub) {
+                (Some(email), _) => format!(" for {}", email),
+                (None, Some(uuid)) => format!(" for account {}", uuid),
+                (None, None) => String::new(),
+            };
+            let expiry = match claims.exp {
+                Some(exp) => format!(
+                    " It expires in {}, use /login to have it renewed automatically.",
+                    queue::format_duration(exp.saturating_sub(queue::unix_time()))
+                ),
+                None => String::new(),
+            };
+            format!(
+                "Token works{}.{} Start sending me some youtube videos.",
+                account, expiry
+            )
         }
         Err(error) => match error.kind {
             crate::types::BotErrorKind::EmptyTokenError => String::from("Please send some text"),
             crate::types::BotErrorKind::InvalidTokenError => {
                 String::from("Please send a valid auth token")
             }
+            crate::types::BotErrorKind::ExpiredTokenError => {
+                String::from("This token has expired. Please send a new one, or use /login")
+            }
+            crate::types::BotErrorKind::UnauthorizedError
+            | crate::types::BotErrorKind::ForbiddenError => {
+                String::from("Pocket Casts didn't accept this token. Please send another one.")
+            }
+            crate::types::BotErrorKind::WebClientError => {
+                String::from("Unable to reach Pocket Casts to check the token. Please send again.")
+            }
             crate::types::BotErrorKind::RedisError => {
                 String::from("Unable to save auth token. Please send again.")
             }
@@ -117,87 +196,1445 @@ pub async fn receive_token(
     Ok(())
 }
 
-pub async fn auth_cancel(
+pub async fn login_initiate(
     bot: teloxide::Bot,
     dialogue: BotDialogue,
     msg: Message,
+    profile: String,
 ) -> Result<(), teloxide::RequestError> {
-    let output = match dialogue.exit().await {
-        Ok(_) => String::from("Authentication cancelled."),
-        Err(_) => String::from("Something went wrong. Please try again."),
+    let profile = match User::parse_profile(&profile) {
+        Ok(value) => value,
+        Err(_) => {
+            bot.send_message(
+                msg.chat.id,
+                "Profile names can only contain letters, numbers, - and _, e.g. /login work",
+            )
+            .await?;
+            return Ok(());
+        }
     };
-    bot.send_message(msg.chat.id, output).await?;
+    bot.send_message(
+        msg.chat.id,
+        "What is your Pocket Casts email? /cancel to stop",
+    )
+    .await?;
+    dialogue
+        .update(CommandState::ReceiveEmail { profile })
+        .await
+        .unwrap();
     Ok(())
 }
 
-pub async fn receive_url(
+pub async fn receive_email(
+    bot: teloxide::Bot,
+    dialogue: BotDialogue,
+    msg: Message,
+    profile: String,
+) -> Result<(), teloxide::RequestError> {
+    let email = msg.text().unwrap_or_default().trim().to_string();
+    if !email.contains('@') {
+        bot.send_message(msg.chat.id, "Please send a valid email address")
+            .await?;
+        return Ok(());
+    }
+    bot.send_message(
+        msg.chat.id,
+        "What is your Pocket Casts password? The message is deleted as soon as it's read. /cancel to stop",
+    )
+    .await?;
+    dialogue
+        .update(CommandState::ReceivePassword { email, profile })
+        .await
+        .unwrap();
+    Ok(())
+}
+
+pub async fn receive_password(
     bot: teloxide::Bot,
+    dialogue: BotDialogue,
     msg: Message,
+    (email, profile): (String, String),
     bot_data: Arc<RwLock<BotData>>,
 ) -> Result<(), teloxide::RequestError> {
-    let mut db_client = bot_data.read().await.db_client.clone();
+    let password = msg.text().unwrap_or_default().to_string();
+    // Don't leave the password in the chat history, whatever happens next
+    bot.delete_message(msg.chat.id, msg.id).await.ok();
     let user_id = match msg.from() {
         Some(msg) => msg.id,
         None => {
-            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+            bot.send_message(msg.chat.id, "Something went wrong. Please send again.")
                 .await?;
             return Ok(());
         }
     };
-    let chat_id = msg.chat.id;
-    let msg_text = msg.text().unwrap_or_default();
 
-    let output = match Queue::add_request(
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
+    let msg_response = match User::login(
         &mut db_client,
+        &pocket_casts,
         user_id.to_string(),
-        chat_id.to_string(),
-        msg_text.to_string(),
+        &profile,
+        &email,
+        &password,
     )
     .await
     {
-        Ok(_) => String::from("Waiting to be processed..."),
+        Ok(account) => {
+            dialogue.exit().await.unwrap();
+            format!(
+                "Logged in as {}. Start sending me some youtube videos.",
+                account
+            )
+        }
         Err(error) => match error.kind {
-            crate::types::BotErrorKind::EmptyTokenError => {
-                String::from("Please set an /auth token before sending URLs.")
-            }
-            crate::types::BotErrorKind::InvalidTokenError => {
-                String::from("Please set a valid /auth token before sending URLs")
+            crate::types::BotErrorKind::EmptyTokenError => String::from("Please send some text"),
+            crate::types::BotErrorKind::UnauthorizedError => {
+                String::from("Wrong email or password. Please send your password again, or /cancel")
             }
-            crate::types::BotErrorKind::InvalidUrlError => {
-                String::from("Please send a valid youtube link.")
+            crate::types::BotErrorKind::EncryptionError => {
+                String::from("Logging in is not available on this bot. Please use /auth instead.")
             }
-            _ => String::from("Unable to process request. Please try again."),
+            _ => String::from("Unable to log in. Please send your password again."),
         },
     };
+    bot.send_message(msg.chat.id, msg_response).await?;
+    Ok(())
+}
+
+pub async fn accounts(
+    bot: teloxide::Bot,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id.to_string(),
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let result = async {
+        let active = User::get_profile(&mut db_client, user_id.clone()).await?;
+        let mut lines = Vec::new();
+        for profile in User::get_profiles(&mut db_client, user_id.clone()).await? {
+            let token =
+                User::get_token(&mut db_client, user::account_id(&user_id, &profile)).await?;
+            let email = uploader::decode_token(&token)
+                .ok()
+                .and_then(|claims| claims.email)
+                .map(|email| format!(" ({})", email))
+                .unwrap_or_default();
+            let marker = if profile == active { " - in use" } else { "" };
+            lines.push(format!("{}{}{}", profile, email, marker));
+        }
+        Ok::<Vec<String>, BotError>(lines)
+    }
+    .await;
+    let output = match result {
+        Ok(lines) if lines.is_empty() => String::from("No accounts yet. To add one: /auth"),
+        Ok(lines) => format!(
+            "Your accounts:\n{}\n\nTo switch: /use <profile>\nTo upload one link elsewhere, send e.g. \"work https://youtu.be/...\"",
+            lines.join("\n")
+        ),
+        Err(_) => String::from("Something went wrong. Please try again."),
+    };
     bot.send_message(msg.chat.id, output).await?;
     Ok(())
 }
 
-pub async fn admin_set_command(
+pub async fn use_profile(
     bot: teloxide::Bot,
     msg: Message,
+    profile: String,
+    bot_data: Arc<RwLock<BotData>>,
 ) -> Result<(), teloxide::RequestError> {
-    bot.set_my_commands(Commands::bot_commands()).await?;
-    bot.send_message(msg.chat.id, "Bot commands updated.")
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    if profile.trim().is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Please send a profile name, e.g. /use work. To see them all: /accounts",
+        )
         .await?;
+        return Ok(());
+    }
+
+    let result = match User::parse_profile(&profile) {
+        Ok(profile) => User::set_profile(&mut db_client, user_id.to_string(), &profile)
+            .await
+            .map(|_| profile),
+        Err(error) => Err(error),
+    };
+    let output = match result {
+        Ok(profile) => format!("Uploading to the {} profile from now on.", profile),
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::InvalidSettingError
+            | crate::types::BotErrorKind::UnknownProfileError => {
+                String::from("There's no profile with this name. To see them all: /accounts")
+            }
+            _ => String::from("Unable to save setting. Please try again."),
+        },
+    };
+    bot.send_message(msg.chat.id, output).await?;
     Ok(())
 }
 
-pub async fn admin_delete_cache(
+pub async fn auth_cancel(
     bot: teloxide::Bot,
+    dialogue: BotDialogue,
     msg: Message,
 ) -> Result<(), teloxide::RequestError> {
-    let path = std::path::Path::new("/tmp/.cache/");
-    let mut reader = tokio::fs::read_dir(path).await?;
-    while let Ok(entry) = reader.next_entry().await {
-        match entry {
-            Some(val) => {
-                tokio::fs::remove_file(val.path()).await?;
+    let output = match dialogue.exit().await {
+        Ok(_) => String::from("Cancelled."),
+        Err(_) => String::from("Something went wrong. Please try again."),
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn usage(
+    bot: teloxide::Bot,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let result =
+        match User::get_active_token(&mut db_client, &pocket_casts, user_id.to_string()).await {
+            Ok(token) => pocket_casts.usage(&token).await,
+            Err(error) => Err(error),
+        };
+    let output = match result {
+        Ok(usage) => format!(
+            "Files storage: {} of {} used, {} left.\nFiles: {}",
+            uploader::format_size(usage.used_size),
+            uploader::format_size(usage.total_size),
+            uploader::format_size(usage.remaining_size()),
+            usage.total_files
+        ),
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::EmptyTokenError => {
+                String::from("Please set an /auth token first.")
             }
-            None => break,
+            crate::types::BotErrorKind::UnauthorizedError => String::from(
+                "Pocket Casts didn't accept your token, it may have expired. Please set a new one with /auth.",
+            ),
+            _ => String::from("Unable to get usage from Pocket Casts. Please try again."),
+        },
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn files(
+    bot: teloxide::Bot,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let result =
+        match User::get_active_token(&mut db_client, &pocket_casts, user_id.to_string()).await {
+            Ok(token) => pocket_casts.list_files(&token).await,
+            Err(error) => Err(error),
+        };
+    match result {
+        Ok(file_list) if file_list.is_empty() => {
+            bot.send_message(msg.chat.id, "You haven't uploaded any files yet.")
+                .await?;
+        }
+        Ok(file_list) => {
+            let (text, keyboard) = files::page(&file_list, 0);
+            bot.send_message(msg.chat.id, text)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        Err(error) => {
+            let output = match error.kind {
+                crate::types::BotErrorKind::EmptyTokenError => {
+                    String::from("Please set an /auth token first.")
+                }
+                crate::types::BotErrorKind::UnauthorizedError => String::from(
+                    "Pocket Casts didn't accept your token, it may have expired. Please set a new one with /auth.",
+                ),
+                _ => String::from("Unable to get your files from Pocket Casts. Please try again."),
+            };
+            bot.send_message(msg.chat.id, output).await?;
         }
     }
-    bot.send_message(msg.chat.id, "Cleared `.cache` folder.")
-        .await?;
+    Ok(())
+}
+
+// Handles the buttons of the /files browser and the "Delete" button under "Done!"
+pub async fn file_callback(
+    bot: teloxide::Bot,
+    query: CallbackQuery,
+    bot_data: Arc<RwLock<BotData>>,
+    storage: Arc<InMemStorage<CommandState>>,
+) -> Result<(), teloxide::RequestError> {
+    let action = query.data.as_deref().and_then(FileAction::parse);
+    let (action, message) = match (action, &query.message) {
+        (Some(action), Some(message)) => (action, message),
+        _ => {
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    let chat_id = message.chat.id;
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
+
+    let user_id = query.from.id.to_string();
+
+    let result = async {
+        // Files may belong to another profile than the active one, e.g. after `/use`
+        let file_profile = match action.uuid() {
+            Some(uuid) => db_client.get_file_profile(user_id.clone(), uuid).await?,
+            None => None,
+        };
+        let profile = match file_profile {
+            Some(profile) => profile,
+            None => User::get_profile(&mut db_client, user_id.clone()).await?,
+        };
+        let account = user::account_id(&user_id, &profile);
+        let token = User::get_fresh_token(&mut db_client, &pocket_casts, account).await?;
+        let protect = matches!(action, FileAction::Protect(_));
+        match action {
+            FileAction::Page(page) => {
+                let file_list = pocket_casts.list_files(&token).await?;
+                let (text, keyboard) = files::page(&file_list, page);
+                bot.edit_message_text(chat_id, message.id, text)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            FileAction::Show(uuid) => {
+                let file = pocket_casts.get_file(&token, &uuid).await?;
+                let protected = db_client.get_protected_files(user_id.clone()).await?;
+                let (text, keyboard) = files::details(&file, protected.contains(&uuid));
+                bot.edit_message_text(chat_id, message.id, text)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            FileAction::Rename(uuid) => {
+                let file = pocket_casts.get_file(&token, &uuid).await?;
+                BotDialogue::new(storage, chat_id)
+                    .update(CommandState::ReceiveFileTitle { uuid, profile })
+                    .await
+                    .unwrap();
+                let output = format!(
+                    "What should \"{}\" be called instead? /cancel to stop",
+                    file.title
+                );
+                bot.send_message(chat_id, output).await?;
+            }
+            FileAction::ChooseColour(uuid) => {
+                bot.edit_message_reply_markup(chat_id, message.id)
+                    .reply_markup(files::colour_keyboard(&uuid))
+                    .await?;
+            }
+            FileAction::SetColour(uuid, colour) => {
+                let mut file = pocket_casts.get_file(&token, &uuid).await?;
+                file.colour = colour;
+                pocket_casts.update_file(&token, &file).await?;
+                let protected = db_client.get_protected_files(user_id.clone()).await?;
+                let (text, keyboard) = files::details(&file, protected.contains(&uuid));
+                bot.edit_message_text(chat_id, message.id, text)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            FileAction::Protect(uuid) | FileAction::Unprotect(uuid) => {
+                let file = pocket_casts.get_file(&token, &uuid).await?;
+                db_client
+                    .set_protected_file(user_id.clone(), uuid.clone(), protect)
+                    .await?;
+                let (text, keyboard) = files::details(&file, protect);
+                bot.edit_message_text(chat_id, message.id, text)
+                    .reply_markup(keyboard)
+                    .await?;
+            }
+            FileAction::Delete(uuid) => {
+                pocket_casts.delete_file(&token, &uuid).await?;
+                db_client
+                    .set_file_profile(user_id.clone(), &uuid, None)
+                    .await
+                    .ok();
+                bot.edit_message_text(chat_id, message.id, "Deleted from your files.")
+                    .await?;
+            }
+        }
+        Ok::<(), BotError>(())
+    }
+    .await;
+
+    let notification = match result {
+        Ok(_) => None,
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::EmptyTokenError => Some("Please set an /auth token first."),
+            crate::types::BotErrorKind::UnauthorizedError => {
+                Some("Pocket Casts didn't accept your token. Please set a new one with /auth.")
+            }
+            crate::types::BotErrorKind::FileNotFoundError => Some("This file no longer exists."),
+            _ => Some("Something went wrong. Please try again."),
+        },
+    };
+    match notification {
+        Some(text) => bot.answer_callback_query(query.id).text(text).await?,
+        None => bot.answer_callback_query(query.id).await?,
+    };
+    Ok(())
+}
+
+pub async fn receive_file_title(
+    bot: teloxide::Bot,
+    dialogue: BotDialogue,
+    msg: Message,
+    (uuid, profile): (String, String),
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please send again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let title = msg.text().unwrap_or_default().trim().to_string();
+    if title.is_empty() {
+        bot.send_message(msg.chat.id, "Please send some text")
+            .await?;
+        return Ok(());
+    }
+
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
+    let result = async {
+        let account = user::account_id(&user_id.to_string(), &profile);
+        let token = User::get_fresh_token(&mut db_client, &pocket_casts, account).await?;
+        let mut file = pocket_casts.get_file(&token, &uuid).await?;
+        file.title = title.clone();
+        pocket_casts.update_file(&token, &file).await
+    }
+    .await;
+    let output = match result {
+        Ok(_) => {
+            dialogue.exit().await.unwrap();
+            format!("Renamed to \"{}\".", title)
+        }
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::FileNotFoundError => {
+                dialogue.exit().await.unwrap();
+                String::from("This file no longer exists.")
+            }
+            _ => String::from("Unable to rename the file. Please send again."),
+        },
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn housekeeping(
+    bot: teloxide::Bot,
+    msg: Message,
+    setting: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let mut words = setting.split_whitespace();
+    let output = match (words.next(), words.next(), words.next()) {
+        (None, _, _) => match User::get_housekeeping(&mut db_client, user_id.to_string()).await {
+            Ok(Some(0)) => String::from("Housekeeping deletes played files, after you confirm. To also delete old files: /housekeeping on <days>"),
+            Ok(Some(days)) => format!("Housekeeping deletes played files and files older than {} days, after you confirm. To stop: /housekeeping off", days),
+            Ok(None) => String::from("Housekeeping is off. To delete played files: /housekeeping on\nTo also delete files older than 30 days: /housekeeping on 30"),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        },
+        (Some("on"), days, None) => {
+            let days = match days.map(|value| value.parse::<u64>()) {
+                None => Ok(0),
+                Some(Ok(value)) => Ok(value),
+                Some(Err(_)) => Err(()),
+            };
+            match days {
+                Ok(days) => match User::set_housekeeping(&mut db_client, user_id.to_string(), Some(days)).await {
+                    Ok(_) => String::from("Housekeeping is on. You'll get a list of files to confirm before anything is deleted. To check now: /housekeeping run"),
+                    Err(_) => String::from("Unable to save setting. Please try again."),
+                },
+                Err(_) => String::from("Please send the number of days, e.g. /housekeeping on 30"),
+            }
+        }
+        (Some("off"), None, _) => {
+            match User::set_housekeeping(&mut db_client, user_id.to_string(), None).await {
+                Ok(_) => String::from("Housekeeping is off."),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+        (Some("run"), None, _) => {
+            match housekeeping::review(&bot, &mut db_client, &pocket_casts, &user_id.to_string()).await {
+                // The summary was sent already
+                Ok(found) if found > 0 => return Ok(()),
+                Ok(_) => String::from("Nothing to clean up."),
+                Err(error) => match error.kind {
+                    crate::types::BotErrorKind::EmptyTokenError => {
+                        String::from("Please set an /auth token first.")
+                    }
+                    crate::types::BotErrorKind::UnauthorizedError => String::from(
+                        "Pocket Casts didn't accept your token, it may have expired. Please set a new one with /auth.",
+                    ),
+                    _ => String::from("Unable to get your files from Pocket Casts. Please try again."),
+                },
+            }
+        }
+        _ => String::from("Please send /housekeeping on, /housekeeping on <days>, /housekeeping off or /housekeeping run"),
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+// Handles the buttons under a housekeeping summary
+pub async fn housekeeping_callback(
+    bot: teloxide::Bot,
+    query: CallbackQuery,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let message = match &query.message {
+        Some(message) => message,
+        None => {
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let pocket_casts = bot_data.read().await.pocket_casts.clone();
+    let user_id = query.from.id.to_string();
+
+    let output = match query.data.as_deref() {
+        Some(housekeeping::CONFIRM_DATA) => {
+            match housekeeping::confirm(&mut db_client, &pocket_casts, &user_id).await {
+                Ok(1) => String::from("Deleted 1 file."),
+                Ok(deleted) => format!("Deleted {} files.", deleted),
+                Err(_) => {
+                    String::from("Unable to delete files. Please try /housekeeping run again.")
+                }
+            }
+        }
+        Some(housekeeping::CANCEL_DATA) => {
+            match housekeeping::cancel(&mut db_client, &user_id).await {
+                Ok(_) => String::from("Kept all files."),
+                Err(_) => String::from("Something went wrong. Please try again."),
+            }
+        }
+        _ => {
+            bot.answer_callback_query(query.id).await?;
+            return Ok(());
+        }
+    };
+    bot.edit_message_text(message.chat.id, message.id, output)
+        .await?;
+    bot.answer_callback_query(query.id).await?;
+    Ok(())
+}
+
+pub async fn cookies_initiate(
+    bot: teloxide::Bot,
+    dialogue: BotDialogue,
+    msg: Message,
+) -> Result<(), teloxide::RequestError> {
+    bot.send_message(
+        msg.chat.id,
+        "Send your cookies.txt file (Netscape format) as a document. /cancel to stop",
+    )
+    .await?;
+    dialogue.update(CommandState::ReceiveCookies).await.unwrap();
+    Ok(())
+}
+
+pub async fn receive_cookies(
+    bot: teloxide::Bot,
+    dialogue: BotDialogue,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please send again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let document = match msg.document() {
+        Some(value) => value,
+        None => {
+            bot.send_message(msg.chat.id, "Please send cookies.txt as a file.")
+                .await?;
+            return Ok(());
+        }
+    };
+    // Cookie files are small, anything bigger is most likely the wrong file
+    if document.file.size > 1024 * 1024 {
+        bot.send_message(msg.chat.id, "File is too large for a cookies.txt file.")
+            .await?;
+        return Ok(());
+    }
+
+    let file = bot.get_file(&document.file.id).await?;
+    let mut cookies = Vec::new();
+    if bot.download_file(&file.path, &mut cookies).await.is_err() {
+        bot.send_message(msg.chat.id, "Unable to download file. Please send again.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let msg_response = match User::set_cookies(&mut db_client, user_id.to_string(), cookies).await {
+        Ok(_) => {
+            dialogue.exit().await.unwrap();
+            String::from("Cookies saved. They will only be used for your downloads.")
+        }
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::InvalidCookiesError
+            | crate::types::BotErrorKind::TypeError => {
+                String::from("Please send a valid Netscape formatted cookies.txt file")
+            }
+            crate::types::BotErrorKind::EncryptionError => {
+                String::from("Cookies are not available on this bot.")
+            }
+            crate::types::BotErrorKind::RedisError => {
+                String::from("Unable to save cookies. Please send again.")
+            }
+            _ => String::from("Something went wrong. Please send again."),
+        },
+    };
+    bot.send_message(msg.chat.id, msg_response).await?;
+    // The cookies grant access to the user's account, so don't leave them in the chat history
+    bot.delete_message(msg.chat.id, msg.id).await.ok();
+    Ok(())
+}
+
+pub async fn cookies_clear(
+    bot: teloxide::Bot,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let output = match User::delete_cookies(&mut db_client, user_id.to_string()).await {
+        Ok(_) => String::from("Cookies removed successfully."),
+        Err(_) => String::from("Unable to remove cookies. Please try again."),
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn subtitles(
+    bot: teloxide::Bot,
+    msg: Message,
+    preference: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let preference = preference.trim();
+    let output = if preference.is_empty() {
+        match User::get_subtitles(&mut db_client, user_id.to_string()).await {
+            Ok(Some(value)) => format!("Transcripts are sent as: {}", value),
+            Ok(None) => String::from("Transcripts are off. To turn on: /subtitles en txt"),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        }
+    } else if preference == "off" {
+        match User::set_subtitles(&mut db_client, user_id.to_string(), None).await {
+            Ok(_) => String::from("Transcripts turned off."),
+            Err(_) => String::from("Unable to save setting. Please try again."),
+        }
+    } else {
+        match SubtitlePreference::parse(preference) {
+            Ok(value) => {
+                let output = format!("Transcripts will be sent as: {}", value);
+                match User::set_subtitles(&mut db_client, user_id.to_string(), Some(value)).await {
+                    Ok(_) => output,
+                    Err(_) => String::from("Unable to save setting. Please try again."),
+                }
+            }
+            Err(_) => String::from(
+                "Please send a language code and format, e.g. /subtitles en txt or /subtitles de srt",
+            ),
+        }
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn video(
+    bot: teloxide::Bot,
+    msg: Message,
+    setting: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let output = match setting.trim() {
+        "" => match User::get_video(&mut db_client, user_id.to_string()).await {
+            Ok(true) => String::from("Videos are uploaded as video. To upload audio: /video off"),
+            Ok(false) => String::from("Videos are uploaded as audio. To upload video: /video on"),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        },
+        value @ ("on" | "off") => {
+            match User::set_video(&mut db_client, user_id.to_string(), value == "on").await {
+                Ok(_) if value == "on" => String::from("Videos will be uploaded as video."),
+                Ok(_) => String::from("Videos will be uploaded as audio."),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+        _ => String::from("Please send /video on or /video off"),
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn feed(
+    bot: teloxide::Bot,
+    msg: Message,
+    setting: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let mut feed = bot_data.read().await.feed.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id.to_string(),
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    if !feed.is_available() {
+        bot.send_message(msg.chat.id, "Feeds are not available on this bot.")
+            .await?;
+        return Ok(());
+    }
+
+    let destinations = match User::get_destinations(&mut db_client, user_id.clone()).await {
+        Ok(value) => value,
+        Err(_) => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let output = match setting.trim() {
+        "" => match destinations.iter().any(|id| id == "feed") {
+            true => match feed.url(&user_id).await {
+                Ok(url) => format!("Uploads go to your private feed. Subscribe to it in any podcast app:\n{}\n\nTo go back to Pocket Casts: /feed off", url),
+                Err(_) => String::from("Something went wrong. Please try again."),
+            },
+            false => String::from("Uploads go to Pocket Casts. To get them in any podcast app instead: /feed on"),
+        },
+        "on" => {
+            // The feed replaces Pocket Casts, other destinations are kept
+            let mut destinations: Vec<String> = destinations
+                .into_iter()
+                .filter(|id| id != destination::DEFAULT_DESTINATION && id != "feed")
+                .collect();
+            destinations.push(String::from("feed"));
+            let result =
+                match User::set_destinations(&mut db_client, user_id.clone(), &destinations).await
+                {
+                    Ok(_) => feed.url(&user_id).await,
+                    Err(error) => Err(error),
+                };
+            match result {
+                Ok(url) => format!("Uploads will go to your private feed. Subscribe to it in any podcast app:\n{}\n\nKeep the link to yourself, anyone with it can listen. For a new link: /feed reset", url),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+        "off" => {
+            match User::remove_destination(&mut db_client, user_id.clone(), "feed").await {
+                Ok(_) => String::from("Uploads will no longer go to your feed. It still has its episodes."),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+        "reset" => match feed.reset(&user_id).await {
+            Ok(url) => format!("Your feed has a new link, the old one no longer works:\n{}", url),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        },
+        _ => String::from("Please send /feed on, /feed off or /feed reset"),
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn destinations(
+    bot: teloxide::Bot,
+    msg: Message,
+    setting: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let feed = bot_data.read().await.feed.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id.to_string(),
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let choice: Vec<String> = setting
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(|id| id.to_lowercase())
+        .collect();
+    let output = if choice.is_empty() {
+        match User::get_destinations(&mut db_client, user_id.clone()).await {
+            Ok(destinations) => format!(
+                "Uploads go to: {}\n\nAvailable: {}\nTo change: /destinations pocketcasts telegram",
+                destinations.join(", "),
+                destination::DESTINATION_IDS.join(", ")
+            ),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        }
+    } else if let Some(id) = choice
+        .iter()
+        .find(|id| !destination::DESTINATION_IDS.contains(&id.as_str()))
+    {
+        format!(
+            "Unknown destination: {}\nAvailable: {}",
+            id,
+            destination::DESTINATION_IDS.join(", ")
+        )
+    } else if choice.iter().any(|id| id == "feed") && !feed.is_available() {
+        String::from("Feeds are not available on this bot.")
+    } else {
+        let mut missing = Vec::new();
+        for id in ["s3", "webdav"] {
+            if !choice.iter().any(|choice| choice == id) {
+                continue;
+            }
+            let configured = match id {
+                "s3" => {
+                    User::get_destination_config::<S3Config>(&mut db_client, user_id.clone(), id)
+                        .await
+                        .map(|config| config.is_some())
+                }
+                _ => User::get_destination_config::<WebDavConfig>(
+                    &mut db_client,
+                    user_id.clone(),
+                    id,
+                )
+                .await
+                .map(|config| config.is_some()),
+            };
+            if !matches!(configured, Ok(true)) {
+                missing.push(format!("/{}", id));
+            }
+        }
+        let mut destinations = Vec::new();
+        for id in choice {
+            if !destinations.contains(&id) {
+                destinations.push(id);
+            }
+        }
+        if !missing.is_empty() {
+            format!("Please set up {} first.", missing.join(" and "))
+        } else {
+            match User::set_destinations(&mut db_client, user_id.clone(), &destinations).await {
+                Ok(_) => format!("Uploads will go to: {}", destinations.join(", ")),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn s3(
+    bot: teloxide::Bot,
+    msg: Message,
+    setting: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id.to_string(),
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let output = match setting.trim() {
+        "" => match User::get_destination_config::<S3Config>(&mut db_client, user_id, "s3").await
+        {
+            Ok(Some(config)) => format!("Uploads to S3 go to {}\n\nTo remove it: /s3 off", config),
+            Ok(None) => String::from("No bucket set. Please send /s3 <endpoint> <bucket> <access key> <secret key> [region] [prefix]"),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        },
+        "off" => {
+            // Uploads would keep failing there without a bucket
+            let result = match User::remove_destination(&mut db_client, user_id.clone(), "s3").await
+            {
+                Ok(_) => {
+                    User::set_destination_config::<S3Config>(&mut db_client, user_id, "s3", None)
+                        .await
+                }
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok(_) => String::from("Bucket removed."),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+        setting => {
+            // The keys grant access to the bucket, so don't leave them in the chat history
+            bot.delete_message(msg.chat.id, msg.id).await.ok();
+            match S3Config::parse(setting) {
+                Ok(config) => match User::set_destination_config(
+                    &mut db_client,
+                    user_id,
+                    "s3",
+                    Some(&config),
+                )
+                .await
+                {
+                    Ok(_) => format!("Bucket set to {}\n\nTo send uploads there: /destinations s3", config),
+                    Err(_) => String::from("Unable to save setting. Please try again."),
+                },
+                Err(_) => String::from("Please send /s3 <endpoint> <bucket> <access key> <secret key> [region] [prefix]"),
+            }
+        }
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn webdav(
+    bot: teloxide::Bot,
+    msg: Message,
+    setting: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id.to_string(),
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let output = match setting.trim() {
+        "" => match User::get_destination_config::<WebDavConfig>(&mut db_client, user_id, "webdav")
+            .await
+        {
+            Ok(Some(config)) => format!(
+                "Uploads to WebDAV go to {}\n\nTo remove it: /webdav off",
+                config
+            ),
+            Ok(None) => String::from(
+                "No folder set. Please send /webdav <folder url> <username> <password>",
+            ),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        },
+        "off" => {
+            // Uploads would keep failing there without a folder
+            let result =
+                match User::remove_destination(&mut db_client, user_id.clone(), "webdav").await {
+                    Ok(_) => {
+                        User::set_destination_config::<WebDavConfig>(
+                            &mut db_client,
+                            user_id,
+                            "webdav",
+                            None,
+                        )
+                        .await
+                    }
+                    Err(error) => Err(error),
+                };
+            match result {
+                Ok(_) => String::from("Folder removed."),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+        setting => {
+            // Don't leave the password in the chat history, whatever happens next
+            bot.delete_message(msg.chat.id, msg.id).await.ok();
+            match WebDavConfig::parse(setting) {
+                Ok(config) => match User::set_destination_config(
+                    &mut db_client,
+                    user_id,
+                    "webdav",
+                    Some(&config),
+                )
+                .await
+                {
+                    Ok(_) => format!(
+                        "Folder set to {}\n\nTo send uploads there: /destinations webdav",
+                        config
+                    ),
+                    Err(_) => String::from("Unable to save setting. Please try again."),
+                },
+                Err(_) => String::from("Please send /webdav <folder url> <username> <password>"),
+            }
+        }
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn up_next(
+    bot: teloxide::Bot,
+    msg: Message,
+    setting: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let output = match setting.trim() {
+        "" => match User::get_up_next(&mut db_client, user_id.to_string()).await {
+            Ok(Some(UpNextPosition::Top)) => {
+                String::from("Uploads are added to the top of Up Next. To stop: /upnext off")
+            }
+            Ok(Some(UpNextPosition::Bottom)) => {
+                String::from("Uploads are added to the bottom of Up Next. To stop: /upnext off")
+            }
+            Ok(None) => String::from(
+                "Uploads aren't added to Up Next. To add them: /upnext top or /upnext bottom",
+            ),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        },
+        "off" => match User::set_up_next(&mut db_client, user_id.to_string(), None).await {
+            Ok(_) => String::from("Uploads won't be added to Up Next."),
+            Err(_) => String::from("Unable to save setting. Please try again."),
+        },
+        value => match UpNextPosition::parse(value) {
+            Some(position) => {
+                match User::set_up_next(&mut db_client, user_id.to_string(), Some(position)).await {
+                    Ok(_) => format!(
+                        "Uploads will be added to the {} of Up Next once they're processed.",
+                        position.as_str()
+                    ),
+                    Err(_) => String::from("Unable to save setting. Please try again."),
+                }
+            }
+            None => String::from("Please send /upnext top, /upnext bottom or /upnext off"),
+        },
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn title(
+    bot: teloxide::Bot,
+    msg: Message,
+    template: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let placeholders = titles::PLACEHOLDERS
+        .iter()
+        .map(|name| format!("{{{}}}", name))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let template = template.trim();
+    let output = if template.is_empty() {
+        match User::get_title_format(&mut db_client, user_id.to_string()).await {
+            Ok(format) => format!(
+                "Uploads are named: {}\n\nPlaceholders: {}\n\nTo change: /title {{upload_date}} {{title}}\nTo go back to the default: /title reset",
+                format.template, placeholders
+            ),
+            Err(_) => String::from("Something went wrong. Please try again."),
+        }
+    } else if template == "reset" {
+        match User::set_title_template(&mut db_client, user_id.to_string(), None).await {
+            Ok(_) => format!("Uploads will be named: {}", titles::DEFAULT_TEMPLATE),
+            Err(_) => String::from("Unable to save setting. Please try again."),
+        }
+    } else {
+        match User::set_title_template(
+            &mut db_client,
+            user_id.to_string(),
+            Some(template.to_string()),
+        )
+        .await
+        {
+            Ok(_) => format!("Uploads will be named: {}", template),
+            Err(error) => match error.kind {
+                crate::types::BotErrorKind::InvalidSettingError => {
+                    format!("Please only use these placeholders: {}", placeholders)
+                }
+                _ => String::from("Unable to save setting. Please try again."),
+            },
+        }
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn title_filter(
+    bot: teloxide::Bot,
+    msg: Message,
+    input: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut filters = match User::get_title_filters(&mut db_client, user_id.to_string()).await {
+        Ok(value) => value,
+        Err(_) => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let input = input.trim();
+    let (action, argument) = input.split_once(' ').unwrap_or((input, ""));
+    let output = match action {
+        "" | "list" => {
+            if filters.is_empty() {
+                String::from("No title filters. To remove \"[Official Video]\" and similar: /titlefilter common")
+            } else {
+                let list = filters
+                    .iter()
+                    .enumerate()
+                    .map(|(index, filter)| format!("{}. {}", index + 1, filter))
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!("Title filters:\n{}\n\nTo remove one: /titlefilter remove 1", list)
+            }
+        }
+        "add" => match TitleFilter::parse(argument) {
+            Ok(filter) => {
+                filters.push(filter);
+                match User::set_title_filters(&mut db_client, user_id.to_string(), filters).await {
+                    Ok(_) => String::from("Title filter added."),
+                    Err(error) => match error.kind {
+                        crate::types::BotErrorKind::InvalidSettingError => format!(
+                            "You can have up to {} title filters.",
+                            titles::MAX_FILTERS
+                        ),
+                        _ => String::from("Unable to save setting. Please try again."),
+                    },
+                }
+            }
+            Err(_) => String::from(
+                "Please send a valid regex, optionally with a replacement, e.g. /titlefilter add Ep\\. (\\d+) => Episode $1",
+            ),
+        },
+        "common" => {
+            for pattern in titles::COMMON_FILTERS {
+                if !filters.iter().any(|filter| filter.pattern == pattern) {
+                    filters.push(TitleFilter {
+                        pattern: pattern.to_string(),
+                        replacement: String::new(),
+                    });
+                }
+            }
+            match User::set_title_filters(&mut db_client, user_id.to_string(), filters).await {
+                Ok(_) => String::from("Common clickbait like \"[Official Video]\", hashtags and emoji will be removed from titles."),
+                Err(_) => String::from("Unable to save setting. Please try again."),
+            }
+        }
+        "remove" => match argument.trim().parse::<usize>() {
+            Ok(number) if number >= 1 && number <= filters.len() => {
+                filters.remove(number - 1);
+                match User::set_title_filters(&mut db_client, user_id.to_string(), filters).await {
+                    Ok(_) => String::from("Title filter removed."),
+                    Err(_) => String::from("Unable to save setting. Please try again."),
+                }
+            }
+            _ => String::from("Please send the number of the filter to remove, see /titlefilter"),
+        },
+        "clear" => match User::set_title_filters(&mut db_client, user_id.to_string(), Vec::new()).await {
+            Ok(_) => String::from("Title filters removed."),
+            Err(_) => String::from("Unable to save setting. Please try again."),
+        },
+        _ => String::from("Please send /titlefilter add, common, remove, clear or list"),
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn receive_url(
+    bot: teloxide::Bot,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let chat_id = msg.chat.id;
+    let msg_text = msg.text().unwrap_or_default();
+
+    let output = match Queue::add_request(
+        &mut db_client,
+        user_id.to_string(),
+        chat_id.to_string(),
+        msg_text.to_string(),
+    )
+    .await
+    {
+        Ok(_) => String::from("Waiting to be processed..."),
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::EmptyTokenError => {
+                String::from("Please set an /auth token before sending URLs.")
+            }
+            crate::types::BotErrorKind::InvalidTokenError => {
+                String::from("Please set a valid /auth token before sending URLs")
+            }
+            crate::types::BotErrorKind::InvalidUrlError => {
+                String::from("Please send a valid link.")
+            }
+            crate::types::BotErrorKind::InvalidSettingError
+            | crate::types::BotErrorKind::UnknownProfileError => {
+                String::from("There's no profile with this name. To see them all: /accounts")
+            }
+            _ => String::from("Unable to process request. Please try again."),
+        },
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn receive_media(
+    bot: teloxide::Bot,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let mut db_client = bot_data.read().await.db_client.clone();
+    let user_id = match msg.from() {
+        Some(msg) => msg.id,
+        None => {
+            bot.send_message(msg.chat.id, "Something went wrong. Please try again.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let (file, media_title) = match filters::media_file(&msg) {
+        Some(value) => value,
+        None => {
+            bot.send_message(msg.chat.id, "Please send an audio, voice or video file.")
+                .await?;
+            return Ok(());
+        }
+    };
+    // The Bot API doesn't let bots download anything bigger
+    if file.size > 20 * 1024 * 1024 {
+        bot.send_message(
+            msg.chat.id,
+            "Files larger than 20 MB can't be downloaded by bots.",
+        )
+        .await?;
+        return Ok(());
+    }
+    // Captions win over file names, e.g. "recording.mp3" becomes "recording"
+    let title = msg
+        .caption()
+        .map(String::from)
+        .or_else(|| {
+            media_title.map(|title| match title.rsplit_once('.') {
+                Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+                _ => title,
+            })
+        })
+        .unwrap_or_else(|| format!("Voice memo {}", msg.date.naive_utc()));
+
+    let output = match Queue::add_upload_request(
+        &mut db_client,
+        user_id.to_string(),
+        msg.chat.id.to_string(),
+        file.id.clone(),
+        title,
+    )
+    .await
+    {
+        Ok(_) => String::from("Waiting to be processed..."),
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::EmptyTokenError => {
+                String::from("Please set an /auth token before sending files.")
+            }
+            _ => String::from("Unable to process request. Please try again."),
+        },
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn admin_set_command(
+    bot: teloxide::Bot,
+    msg: Message,
+) -> Result<(), teloxide::RequestError> {
+    bot.set_my_commands(Commands::bot_commands()).await?;
+    bot.send_message(msg.chat.id, "Bot commands updated.")
+        .await?;
+    Ok(())
+}
+
+pub async fn admin_delete_cache(
+    bot: teloxide::Bot,
+    msg: Message,
+) -> Result<(), teloxide::RequestError> {
+    let path = std::path::Path::new("/tmp/.cache/");
+    let mut reader = tokio::fs::read_dir(path).await?;
+    while let Ok(entry) = reader.next_entry().await {
+        match entry {
+            Some(val) => {
+                tokio::fs::remove_file(val.path()).await?;
+            }
+            None => break,
+        }
+    }
+    bot.send_message(msg.chat.id, "Cleared `.cache` folder.")
+        .await?;
+    Ok(())
+}
+
+pub async fn admin_yt_dlp_version(
+    bot: teloxide::Bot,
+    msg: Message,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let updater = bot_data.read().await.yt_dlp_updater.clone();
+    let output = match updater.installed_version().await {
+        Ok(version) => format!("Installed yt-dlp version: {}", version),
+        Err(_) => String::from("Unable to run yt-dlp."),
+    };
+    bot.send_message(msg.chat.id, output).await?;
+    Ok(())
+}
+
+pub async fn admin_yt_dlp_update(
+    bot: teloxide::Bot,
+    msg: Message,
+    version: String,
+    bot_data: Arc<RwLock<BotData>>,
+) -> Result<(), teloxide::RequestError> {
+    let version = version.trim();
+    if version.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            "Please include a version, e.g. /ytdlpupdate 2023.03.04",
+        )
+        .await?;
+        return Ok(());
+    }
+    bot.send_message(msg.chat.id, format!("Installing yt-dlp {}...", version))
+        .await?;
+    let updater = bot_data.read().await.yt_dlp_updater.clone();
+    let output = match updater.switch_version(version).await {
+        Ok(installed) => format!("Now using yt-dlp {}.", installed),
+        Err(error) => match error.kind {
+            crate::types::BotErrorKind::InvalidVersionError => {
+                String::from("Unable to find that version on the mirror.")
+            }
+            crate::types::BotErrorKind::ChecksumError => {
+                String::from("Checksum did not match. Keeping the current version.")
+            }
+            crate::types::BotErrorKind::InvalidSettingError => String::from(
+                "Set YT_DLP_SMOKE_TEST_FILE to a local media file first, so new versions can be tested before switching.",
+            ),
+            crate::types::BotErrorKind::SmokeTestError => {
+                String::from("New version failed the smoke test. Keeping the current version.")
+            }
+            _ => String::from("Unable to install yt-dlp. Keeping the current version."),
+        },
+    };
+    bot.send_message(msg.chat.id, output).await?;
     Ok(())
 }
@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use teloxide::{
+    payloads::SendMessageSetters,
+    requests::Requester,
+    types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup},
+    Bot,
+};
+
+use crate::{
+    bot::ConfigParameters,
+    database::Database,
+    types::{BotError, BotErrorKind, BotResult},
+    uploader::{self, PocketCastsClient, PocketCastsFile},
+    user::{account_id, User},
+};
+
+pub const CONFIRM_DATA: &str = "housekeeping:confirm";
+pub const CANCEL_DATA: &str = "housekeeping:cancel";
+
+// Characters of the summary spent on listing files, leaves room for the rest of the message
+const MAX_LIST_LENGTH: usize = 3500;
+
+// Periodically looks for files that can go for every user who turned housekeeping on
+pub struct Housekeeper {
+    bot: Bot,
+    database: Database,
+    pocket_casts: PocketCastsClient,
+    interval: Duration,
+}
+
+impl Housekeeper {
+    pub fn new(
+        bot: Bot,
+        database: Database,
+        pocket_casts: PocketCastsClient,
+        cfg: &ConfigParameters,
+    ) -> Self {
+        Housekeeper {
+            bot,
+            database,
+            pocket_casts,
+            interval: Duration::from_secs(cfg.housekeeping_interval),
+        }
+    }
+
+    pub fn start(mut self) {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(self.interval).await;
+                let user_ids = match self.database.get_housekeeping_users().await {
+                    Ok(value) => value,
+                    Err(_) => continue,
+                };
+                for user_id in user_ids {
+                    review(&self.bot, &mut self.database, &self.pocket_casts, &user_id)
+                        .await
+                        .ok();
+                }
+            }
+        });
+    }
+}
+
+// Finds files that can be deleted and asks the user to confirm, nothing is deleted yet.
+// Returns how many files were found.
+pub async fn review(
+    bot: &Bot,
+    database: &mut Database,
+    pocket_casts: &PocketCastsClient,
+    user_id: &str,
+) -> BotResult<usize> {
+    let max_age_days = match User::get_housekeeping(database, user_id.to_string()).await? {
+        Some(value) => value,
+        None => return Ok(0),
+    };
+    let profiles = User::get_profiles(database, user_id.to_string()).await?;
+    if profiles.is_empty() {
+        return Err(BotError::new(BotErrorKind::EmptyTokenError));
+    }
+    let protected = database.get_protected_files(user_id.to_string()).await?;
+    // Every profile is cleaned, not only the one `/use` points at
+    let mut accounts = Vec::new();
+    for profile in profiles {
+        let account = account_id(user_id, &profile);
+        let token = User::get_fresh_token(database, pocket_casts, account).await?;
+        accounts.push((profile, pocket_casts.list_files(&token).await?));
+    }
+    let found = accounts
+        .iter()
+        .flat_map(|(profile, files)| {
+            candidates(files, &protected, max_age_days)
+                .into_iter()
+                .map(move |(file, reason)| (profile.as_str(), file, reason))
+        })
+        .collect::<Vec<(&str, &PocketCastsFile, String)>>();
+    if found.is_empty() {
+        return Ok(0);
+    }
+
+    // Each uuid is stored with its profile, so confirming deletes from the account reviewed
+    let pending = found
+        .iter()
+        .map(|(profile, file, _)| format!("{}:{}", profile, file.uuid))
+        .collect::<Vec<String>>()
+        .join(",");
+    database
+        .set_setting(user_id.to_string(), "housekeeping_pending", pending)
+        .await?;
+    let total_size: u64 = found.iter().filter_map(|(_, file, _)| file.size).sum();
+    let several_profiles = accounts.len() > 1;
+    // Telegram messages are limited to 4096 characters, a full storage can hold far more titles
+    let mut list = String::new();
+    for (index, (profile, file, reason)) in found.iter().enumerate() {
+        let line = if several_profiles {
+            format!("- {} ({}, {})\n", file.title, profile, reason)
+        } else {
+            format!("- {} ({})\n", file.title, reason)
+        };
+        if list.chars().count() + line.chars().count() > MAX_LIST_LENGTH {
+            list.push_str(&format!("…and {} more\n", found.len() - index));
+            break;
+        }
+        list.push_str(&line);
+    }
+    let output = format!(
+        "Housekeeping found {} files to delete, {} in total:\n{}\nNothing is deleted until you confirm.",
+        found.len(),
+        uploader::format_size(total_size),
+        list
+    );
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![
+        InlineKeyboardButton::callback(format!("Delete {} files", found.len()), CONFIRM_DATA),
+        InlineKeyboardButton::callback("Keep them", CANCEL_DATA),
+    ]]);
+    let chat_id = match user_id.parse::<i64>() {
+        Ok(value) => ChatId(value),
+        Err(_) => return Ok(0),
+    };
+    bot.send_message(chat_id, output)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(found.len())
+}
+
+// Deletes the files from the last summary, as long as they still qualify. Returns how many
+// were deleted.
+pub async fn confirm(
+    database: &mut Database,
+    pocket_casts: &PocketCastsClient,
+    user_id: &str,
+) -> BotResult<usize> {
+    let pending = database
+        .get_setting(user_id.to_string(), "housekeeping_pending")
+        .await?
+        .unwrap_or_default();
+    database
+        .delete_setting(user_id.to_string(), "housekeeping_pending")
+        .await?;
+    let max_age_days = match User::get_housekeeping(database, user_id.to_string()).await? {
+        Some(value) => value,
+        None => return Ok(0),
+    };
+    // The user may have starred or protected something since the summary was sent
+    let protected = database.get_protected_files(user_id.to_string()).await?;
+    let mut profiles: Vec<&str> = Vec::new();
+    for (profile, _) in pending.split(',').filter_map(|entry| entry.split_once(':')) {
+        if !profiles.contains(&profile) {
+            profiles.push(profile);
+        }
+    }
+    let mut deleted = 0;
+    for profile in profiles {
+        let account = account_id(user_id, profile);
+        let token = User::get_fresh_token(database, pocket_casts, account).await?;
+        let files = pocket_casts.list_files(&token).await?;
+        for (file, _) in candidates(&files, &protected, max_age_days) {
+            if pending
+                .split(',')
+                .any(|entry| entry.split_once(':') == Some((profile, file.uuid.as_str())))
+            {
+                pocket_casts.delete_file(&token, &file.uuid).await?;
+                deleted += 1;
+            }
+        }
+    }
+    Ok(deleted)
+}
+
+pub async fn cancel(database: &mut Database, user_id: &str) -> BotResult<()> {
+    database
+        .delete_setting(user_id.to_string(), "housekeeping_pending")
+        .await
+}
+
+// Files that are played, or older than `max_age_days` when it isn't 0, along with the reason.
+// Starred and protected files are always kept.
+fn candidates<'a>(
+    files: &'a [PocketCastsFile],
+    protected: &[String],
+    max_age_days: u64,
+) -> Vec<(&'a PocketCastsFile, String)> {
+    let now = Utc::now();
+    files
+        .iter()
+        .filter(|file| !file.starred && !protected.contains(&file.uuid))
+        .filter_map(|file| {
+            if file.is_played() {
+                return Some((file, String::from("played")));
+            }
+            let published = file
+                .published
+                .as_deref()
+                .and_then(|value| DateTime::parse_from_rfc3339(value).ok())?;
+            let age_days = (now - published.with_timezone(&Utc)).num_days();
+            if max_age_days > 0 && age_days >= max_age_days as i64 {
+                return Some((file, format!("{} days old", age_days)));
+            }
+            None
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(uuid: &str, days_old: i64) -> PocketCastsFile {
+        PocketCastsFile {
+            uuid: uuid.to_string(),
+            title: uuid.to_string(),
+            size: Some(1024),
+            duration: Some(100.0),
+            colour: 0,
+            published: Some((Utc::now() - chrono::Duration::days(days_old)).to_rfc3339()),
+            playing_status: None,
+            played_up_to: None,
+            starred: false,
+        }
+    }
+
+    fn uuids(found: Vec<(&PocketCastsFile, String)>) -> Vec<(String, String)> {
+        found
+            .into_iter()
+            .map(|(file, reason)| (file.uuid.clone(), reason))
+            .collect()
+    }
+
+    #[test]
+    fn finds_played_and_old_files() {
+        let mut played = file("played", 1);
+        played.playing_status = Some(3);
+        let files = vec![played, file("old", 40), file("new", 2)];
+        assert_eq!(
+            uuids(candidates(&files, &[], 30)),
+            vec![
+                (String::from("played"), String::from("played")),
+                (String::from("old"), String::from("40 days old")),
+            ]
+        );
+        // 0 only deletes played files
+        assert_eq!(candidates(&files, &[], 0).len(), 1);
+    }
+
+    #[test]
+    fn keeps_starred_and_protected_files() {
+        let mut starred = file("starred", 40);
+        starred.starred = true;
+        let files = vec![starred, file("protected", 40)];
+        assert!(candidates(&files, &[String::from("protected")], 30).is_empty());
+    }
+}
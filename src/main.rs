@@ -1,29 +1,53 @@
+use std::path::PathBuf;
+
 use warp::Filter;
 
 mod bot;
+mod crypto;
 mod database;
+mod destination;
 mod downloader;
+mod feed;
+mod files;
 mod filters;
 mod handlers;
+mod housekeeping;
+mod process;
+mod proxy;
 mod queue;
+mod splitter;
+mod subtitles;
+mod titles;
 mod types;
+mod updater;
 mod uploader;
 mod user;
+mod watchdog;
+
+// The uploader is tested against the mock server
+#[cfg(test)]
+#[allow(dead_code)]
+#[path = "bin/mock_pocket_casts.rs"]
+mod mock_pocket_casts;
 
 #[tokio::main]
 async fn main() {
     // Load environment varilable from .env if available
     dotenvy::dotenv().ok();
 
-    // Fly.io requires a webserver to determine availability
-    tokio::spawn(run_webserver());
+    let parameters =
+        envy::from_env::<bot::ConfigParameters>().expect("Failed to parse config parameters");
+    // Fly.io requires a webserver to determine availability, it also serves feeds
+    tokio::spawn(run_webserver(parameters.feed_path));
 
     bot::run().await;
 }
 
-async fn run_webserver() {
+async fn run_webserver(feed_path: PathBuf) {
     println!("Starting webserver...");
-    let routes = warp::any().map(|| "Hello, World!");
+    // Feeds and their episodes, warp takes care of range requests
+    let feeds = warp::path(feed::FEEDS_ROUTE).and(warp::fs::dir(feed_path));
+    let routes = feeds.or(warp::any().map(|| "Hello, World!"));
     warp::serve(routes)
         .run(([0, 0, 0, 0, 0, 0, 0, 0], 8080))
         .await;
@@ -0,0 +1,101 @@
+use std::{
+    os::unix::process::CommandExt,
+    process::{Command as StdCommand, Output, Stdio},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
+
+use crate::{
+    bot::ConfigParameters,
+    types::{BotError, BotErrorKind, BotResult},
+    watchdog::Heartbeat,
+};
+
+// How long each stage of a request may take before its processes are killed
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    pub metadata: Duration,
+    pub download: Duration,
+    pub postprocess: Duration,
+}
+
+impl Timeouts {
+    pub fn new(cfg: &ConfigParameters) -> Self {
+        Timeouts {
+            metadata: Duration::from_secs(cfg.metadata_timeout),
+            download: Duration::from_secs(cfg.download_timeout),
+            postprocess: Duration::from_secs(cfg.postprocess_timeout),
+        }
+    }
+}
+
+// Runs the command in its own process group, so the whole group can be killed if it hangs.
+// Every line of output counts as progress for the heartbeat.
+pub async fn run(
+    mut command: StdCommand,
+    timeout: Duration,
+    heartbeat: Option<&Heartbeat>,
+) -> BotResult<Output> {
+    command
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = Command::from(command).kill_on_drop(true).spawn()?;
+    let process_group = child.id();
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+
+    let result = tokio::time::timeout(timeout, async {
+        let (stdout, stderr, status) = tokio::join!(
+            read_output(stdout, heartbeat),
+            read_output(stderr, heartbeat),
+            child.wait()
+        );
+        Ok::<Output, BotError>(Output {
+            status: status?,
+            stdout: stdout?,
+            stderr: stderr?,
+        })
+    })
+    .await;
+
+    match result {
+        Ok(output) => output,
+        Err(_) => {
+            if let Some(process_group) = process_group {
+                kill_group(process_group);
+            }
+            child.wait().await.ok();
+            Err(BotError::new(BotErrorKind::TimeoutError))
+        }
+    }
+}
+
+async fn read_output(
+    output: Option<impl AsyncRead + Unpin>,
+    heartbeat: Option<&Heartbeat>,
+) -> BotResult<Vec<u8>> {
+    let mut buffer = Vec::new();
+    let mut reader = match output {
+        Some(value) => BufReader::new(value),
+        None => return Ok(buffer),
+    };
+    while reader.read_until(b'\n', &mut buffer).await? > 0 {
+        if let Some(heartbeat) = heartbeat {
+            heartbeat.beat();
+        }
+    }
+    Ok(buffer)
+}
+
+fn kill_group(process_group: u32) {
+    // SAFETY: killpg has no memory safety requirements, at worst the group no longer exists
+    unsafe {
+        libc::killpg(process_group as libc::pid_t, libc::SIGKILL);
+    }
+}
@@ -1,11 +1,24 @@
-use teloxide::{requests::Requester, Bot};
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use teloxide::{payloads::SendMessageSetters, requests::Requester, types::InputFile, Bot};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
 
 use crate::{
+    bot::ConfigParameters,
     database::Database,
-    downloader,
+    destination::{self, Delivery, Destination},
+    downloader::{
+        self, telegram, DownloadOptions, DownloadedMedia, Downloader, HttpDownloader, MediaInfo,
+        DOWNLOAD_PATH,
+    },
+    proxy::{self, ProxyPool},
     types::{BotError, BotErrorKind, BotResult},
-    uploader,
-    user::User,
+    user::{self, User},
+    watchdog::Watchdog,
 };
 
 // From: https://docs.rs/once_cell/latest/once_cell/
@@ -17,67 +30,376 @@ macro_rules! regex {
     }};
 }
 
+#[derive(Clone)]
 pub struct Queue {
     bot: Bot,
     database: Database,
+    downloader: Arc<dyn Downloader>,
+    proxies: Arc<ProxyPool>,
+    destinations: Arc<Vec<Arc<dyn Destination>>>,
+    watchdog: Watchdog,
+    config: ConfigParameters,
+}
+
+// What happened to a request once a worker is done with it
+pub enum RequestOutcome {
+    Completed,
+    // Parked until a live stream or premiere can be downloaded
+    Scheduled,
+}
+
+// A link sent to the bot, along with anything in front of it
+pub struct ParsedRequest<'a> {
+    // Video or audio for this link only
+    pub video: Option<bool>,
+    pub profile: Option<&'a str>,
+    pub url: &'a str,
 }
 
 impl Queue {
-    pub async fn new(bot: Bot, database: Database) -> Self {
-        Queue { bot, database }
+    pub async fn new(
+        bot: Bot,
+        database: Database,
+        downloader: Arc<dyn Downloader>,
+        proxies: ProxyPool,
+        config: ConfigParameters,
+    ) -> Self {
+        Queue {
+            bot: bot.clone(),
+            database: database.clone(),
+            downloader,
+            proxies: Arc::new(proxies),
+            destinations: Arc::new(destination::from_config(
+                &config,
+                bot.clone(),
+                database.clone(),
+            )),
+            watchdog: Watchdog::default(),
+            config,
+        }
     }
 
     pub async fn start(&self, workers: u64) {
+        self.watchdog.start(
+            self.bot.clone(),
+            self.config.admin_user_ids.clone(),
+            Duration::from_secs(self.config.watchdog_stall_minutes * 60),
+        );
+
         for _ in 0..workers {
-            let bot = self.bot.clone();
-            let mut database = self.database.clone();
+            let mut queue = self.clone();
 
             tokio::spawn(async move {
-                // TODO: Better error handling / reporting
                 loop {
-                    let (_key, processing_id) = match database.wait_for_request().await {
+                    let (_key, processing_id) = match queue.database.wait_for_request().await {
                         Ok((key, value)) => (key, value),
                         Err(_) => continue,
                     };
-                    let request = Queue::get_request(&mut database, &processing_id).await;
+                    let request = Queue::get_request(&mut queue.database, &processing_id).await;
+                    if request.len() < 3 {
+                        continue;
+                    }
                     let user_id = request[0].to_string();
-                    let token = match User::get_token(&mut database, user_id).await {
+                    let result = queue.run_request(&processing_id, user_id, &request).await;
+                    let outcome = match result {
                         Ok(value) => value,
-                        Err(_) => continue,
+                        Err(error) => {
+                            queue
+                                .handle_failure(&processing_id, &request[1], &error)
+                                .await
+                                .ok();
+                            continue;
+                        }
                     };
-                    match Queue::processing_request(&bot, &token, &request).await {
-                        Ok(_) => (),
-                        Err(_) => continue,
+                    match outcome {
+                        RequestOutcome::Completed => (),
+                        // The request is still needed once it's picked up again
+                        RequestOutcome::Scheduled => continue,
                     }
-                    match Queue::delete_request(&mut database, &processing_id).await {
+                    match Queue::delete_request(&mut queue.database, &processing_id).await {
                         Ok(_) => (),
                         Err(_) => continue,
                     };
                 }
             });
         }
+
+        // Moves parked requests back onto the queue once it's time to check on them again
+        let mut queue = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(queue.config.scheduler_interval)).await;
+                let due = match queue.database.get_due_scheduled(unix_time()).await {
+                    Ok(value) => value,
+                    Err(_) => continue,
+                };
+                for processing_id in due {
+                    queue.database.requeue_scheduled(&processing_id).await.ok();
+                }
+            }
+        });
+    }
+
+    async fn run_request(
+        &mut self,
+        processing_id: &str,
+        user_id: String,
+        request: &[String],
+    ) -> BotResult<RequestOutcome> {
+        let mut options = self.download_options(user_id, processing_id).await?;
+        options.heartbeat = Some(self.watchdog.watch(processing_id, &request[0], &request[2]));
+        let result = self
+            .processing_request(processing_id, request, &options)
+            .await;
+        self.watchdog.unwatch(processing_id);
+        Queue::cleanup_options(&options).await;
+
+        // The request is deleted once it's done, so the proxy is logged along with the outcome
+        let outcome = match &result {
+            Ok(RequestOutcome::Completed) => String::from("completed"),
+            Ok(RequestOutcome::Scheduled) => String::from("scheduled"),
+            Err(error) => format!("failed with {:?}", error.kind),
+        };
+        println!(
+            "Request {} {} via {}",
+            processing_id,
+            outcome,
+            proxy_name(&options.proxy)
+        );
+        // Only count errors that could be caused by the proxy against it
+        if let Some(proxy) = &options.proxy {
+            match &result {
+                Ok(_) => self.proxies.report_success(proxy),
+                Err(error) => {
+                    if matches!(
+                        error.kind,
+                        BotErrorKind::DownloadError
+                            | BotErrorKind::RateLimitedError
+                            | BotErrorKind::TimeoutError
+                    ) {
+                        self.proxies.report_failure(proxy);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    // Lets the user know what went wrong, and retries with a growing delay if it may help
+    async fn handle_failure(
+        &mut self,
+        processing_id: &String,
+        chat_id: &str,
+        error: &BotError,
+    ) -> BotResult<()> {
+        let attempts = self
+            .database
+            .increment_attempts(processing_id.to_string())
+            .await?;
+        // Running it again would deliver everything a second time
+        let delivered = self
+            .database
+            .get_request_field(processing_id.to_string(), "delivered")
+            .await?
+            .is_some();
+        let mut output = error.kind.failure_message().to_string();
+        if error.kind.is_retryable() && !delivered && attempts < self.config.max_attempts {
+            let wait = retry_delay(self.config.retry_delay, attempts);
+            output = format!("{} Trying again in {}.", output, format_duration(wait));
+            self.database
+                .delay_request(processing_id.to_string(), unix_time() + wait)
+                .await?;
+        } else {
+            Queue::delete_request(&mut self.database, processing_id).await?;
+        }
+        self.bot.send_message(chat_id.to_string(), output).await?;
+        Ok(())
     }
 
     pub async fn processing_request(
-        bot: &Bot,
-        token: &String,
-        data: &Vec<String>,
+        &mut self,
+        processing_id: &str,
+        data: &[String],
+        options: &DownloadOptions,
+    ) -> BotResult<RequestOutcome> {
+        let chat_id = data[1].clone();
+        let mut info = self.downloader.fetch_info(&data[2], options).await?;
+        // Files sent directly to the bot come with their own title
+        if let Some(title) = self
+            .database
+            .get_request_field(processing_id.to_string(), "title")
+            .await?
+        {
+            info.title = title;
+            info.channel = None;
+        }
+        // Set to the stream's status whenever the request was parked
+        let previous_status = self
+            .database
+            .get_request_field(processing_id.to_string(), "scheduled")
+            .await?;
+
+        if let Some(wait) = live_wait(&info, self.config.live_recheck_interval) {
+            let output = match info.live_status.as_deref() {
+                Some("is_upcoming") => format!(
+                    "This stream hasn't started yet. Checking again in {}.",
+                    format_duration(wait)
+                ),
+                Some("is_live") => format!(
+                    "This stream is live. It will be downloaded once it's over, checking again in {}.",
+                    format_duration(wait)
+                ),
+                _ => format!(
+                    "This stream has ended but the recording isn't ready yet. Checking again in {}.",
+                    format_duration(wait)
+                ),
+            };
+            // Only let the user know when something changed, not on every check
+            let status = info
+                .live_status
+                .clone()
+                .unwrap_or_else(|| String::from("is_live"));
+            self.database
+                .schedule_request(
+                    processing_id.to_string(),
+                    status.clone(),
+                    unix_time() + wait,
+                )
+                .await?;
+            if previous_status.as_deref() != Some(status.as_str()) {
+                self.bot.send_message(chat_id, output).await?;
+            }
+            return Ok(RequestOutcome::Scheduled);
+        }
+
+        if previous_status.is_some() {
+            self.bot
+                .send_message(chat_id.clone(), "The recording is available now.")
+                .await?;
+        }
+        self.bot
+            .send_message(chat_id.clone(), "Downloading...")
+            .await?;
+        let download_result =
+            downloader::download_media(self.downloader.as_ref(), &data[2], &info, options).await;
+        let media = download_result?;
+        let result = self
+            .deliver_media(processing_id, data, &info, &media, options)
+            .await;
+        // The transcript is only kept until it's delivered or the delivery failed
+        if let Some(subtitles_path) = &media.subtitles_path {
+            tokio::fs::remove_file(subtitles_path).await.ok();
+        }
+        result?;
+        Ok(RequestOutcome::Completed)
+    }
+
+    // Sends the media to every destination the user picked, then the transcript to the chat
+    async fn deliver_media(
+        &mut self,
+        processing_id: &str,
+        data: &[String],
+        info: &MediaInfo,
+        media: &DownloadedMedia,
+        options: &DownloadOptions,
     ) -> BotResult<()> {
-        bot.send_message(data[1].clone(), "Downloading...").await?;
-        let file_info = downloader::download_audio(&data[2]).await?;
-        bot.send_message(data[1].clone(), String::from("Uploading..."))
+        let chat_id = data[1].clone();
+        let destination_ids = User::get_destinations(&mut self.database, data[0].clone()).await?;
+        let delivery = Delivery {
+            processing_id,
+            user_id: &data[0],
+            chat_id: &chat_id,
+            url: &data[2],
+            info,
+            media,
+            options,
+        };
+        let (report, keyboard) =
+            destination::deliver_all(&self.destinations, &destination_ids, &delivery).await?;
+        // Anything failing from here on is only reported, not retried
+        self.database
+            .set_request_field(processing_id.to_string(), "delivered", String::from("1"))
             .await?;
-        uploader::upload_audio(&token, &file_info.0, &file_info.1).await?;
-        bot.send_message(data[1].clone(), String::from("Done!"))
+        self.bot
+            .send_message(chat_id.clone(), report)
+            .reply_markup(keyboard)
             .await?;
+        // Destinations have no notes to attach the transcript to, so it's sent in the chat
+        if let Some(preference) = &options.subtitles {
+            match &media.subtitles_path {
+                Some(subtitles_path) => {
+                    let file_name = format!("{}.{}", media.title, preference.extension());
+                    let document = InputFile::file(subtitles_path).file_name(file_name);
+                    self.bot.send_document(chat_id, document).await?;
+                }
+                None => {
+                    let output = format!("No subtitles found in \"{}\".", preference.language);
+                    self.bot.send_message(chat_id, output).await?;
+                }
+            }
+        }
         Ok(())
     }
 
-    pub async fn get_request(database: &mut Database, processing_id: &String) -> Vec<String> {
-        match database.get_request(processing_id.to_string()).await {
-            Ok(vec) => vec,
-            Err(_) => Vec::new(),
+    // Picks a proxy and writes the user's cookies to a file only readable by the bot,
+    // for the duration of the job
+    async fn download_options(
+        &mut self,
+        user_id: String,
+        processing_id: &str,
+    ) -> BotResult<DownloadOptions> {
+        let database = &mut self.database;
+        // The mode picked for this request wins over the user's setting
+        let video = match database
+            .get_request_field(processing_id.to_string(), "video")
+            .await?
+        {
+            Some(value) => value == "on",
+            None => User::get_video(database, user_id.clone()).await?,
+        };
+        let mut options = DownloadOptions {
+            proxy: self.proxies.next(),
+            subtitles: User::get_subtitles(database, user_id.clone()).await?,
+            video_max_height: video.then_some(self.config.video_max_height),
+            title_format: User::get_title_format(database, user_id.clone()).await?,
+            ..Default::default()
+        };
+        // Keep track of which proxy served the job
+        database
+            .set_request_field(
+                processing_id.to_string(),
+                "proxy",
+                proxy_name(&options.proxy),
+            )
+            .await?;
+        if let Some(cookies) = User::get_cookies(database, user_id).await? {
+            let file_name = format!("cookies-{}.txt", processing_id.replace(':', "-"));
+            let cookies_path = Path::new(DOWNLOAD_PATH).join(file_name);
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&cookies_path)
+                .await?;
+            file.write_all(&cookies).await?;
+            file.flush().await?;
+            options.cookies_path = Some(cookies_path);
         }
+        Ok(options)
+    }
+
+    async fn cleanup_options(options: &DownloadOptions) {
+        if let Some(cookies_path) = &options.cookies_path {
+            tokio::fs::remove_file(cookies_path).await.ok();
+        }
+    }
+
+    pub async fn get_request(database: &mut Database, processing_id: &String) -> Vec<String> {
+        database
+            .get_request(processing_id.to_string())
+            .await
+            .unwrap_or_default()
     }
 
     pub async fn add_request(
@@ -86,16 +408,96 @@ impl Queue {
         chat_id: String,
         msg_text: String,
     ) -> BotResult<()> {
-        // Verify user has a token before adding to queue
-        User::get_token(database, user_id.to_string()).await?;
+        let request = Queue::parse_request(&msg_text);
+        let profile = match request.profile {
+            Some(name) => {
+                let profile = User::parse_profile(name)?;
+                if !User::get_profiles(database, user_id.clone())
+                    .await?
+                    .contains(&profile)
+                {
+                    return Err(BotError::new(BotErrorKind::UnknownProfileError));
+                }
+                profile
+            }
+            None => User::get_profile(database, user_id.clone()).await?,
+        };
+        // Verify user has a token before adding to queue, unless it doesn't go to Pocket Casts
+        if User::get_destinations(database, user_id.clone())
+            .await?
+            .iter()
+            .any(|id| id == destination::DEFAULT_DESTINATION)
+        {
+            User::get_token(database, user::account_id(&user_id, &profile)).await?;
+        }
         // Dirty attempt at catching non-youtube links before sending them off to process
         let yt_regex = regex!(
-            r#"(?:https?://)?(?:youtu\.be/|(?:www\.|m\.)?youtube\.com/(?:watch|v|embed)(?:\.php)?(?:\?.*v=|/))([a-zA-Z0-9_-]+)"#
+            r#"(?:https?://)?(?:youtu\.be/|(?:www\.|m\.)?youtube\.com/(?:watch|v|embed|live)(?:\.php)?(?:\?.*v=|/))([a-zA-Z0-9_-]+)"#
         );
-        if !yt_regex.is_match(&msg_text) {
+        if !yt_regex.is_match(request.url) && !HttpDownloader::is_media_url(request.url) {
             return Err(BotError::new(BotErrorKind::InvalidUrlError));
         }
-        database.add_request(user_id, chat_id, msg_text).await?;
+        // The profile is picked now, switching with /use doesn't move queued requests
+        let mut extra_fields = vec![("profile", profile)];
+        if let Some(video) = request.video {
+            let value = if video { "on" } else { "off" };
+            extra_fields.push(("video", value.to_string()));
+        }
+        database
+            .add_request(user_id, chat_id, request.url.to_string(), extra_fields)
+            .await?;
+        Ok(())
+    }
+
+    // Requests may start with "video" or "audio" to override the user's setting for one link,
+    // and with a profile name to upload to another account, e.g. "work video https://youtu.be/abc"
+    pub fn parse_request(msg_text: &str) -> ParsedRequest<'_> {
+        let mut request = ParsedRequest {
+            video: None,
+            profile: None,
+            url: msg_text.trim(),
+        };
+        while let Some((word, rest)) = request.url.split_once(char::is_whitespace) {
+            if word.eq_ignore_ascii_case("video") && request.video.is_none() {
+                request.video = Some(true);
+            } else if word.eq_ignore_ascii_case("audio") && request.video.is_none() {
+                request.video = Some(false);
+            } else if !word.starts_with("http") && request.profile.is_none() {
+                request.profile = Some(word);
+            } else {
+                break;
+            }
+            request.url = rest.trim_start();
+        }
+        request
+    }
+
+    // Queues a file sent directly to the bot, the title is used as is
+    pub async fn add_upload_request(
+        database: &mut Database,
+        user_id: String,
+        chat_id: String,
+        file_id: String,
+        title: String,
+    ) -> BotResult<()> {
+        let profile = User::get_profile(database, user_id.clone()).await?;
+        // Verify user has a token before adding to queue, unless it doesn't go to Pocket Casts
+        if User::get_destinations(database, user_id.clone())
+            .await?
+            .iter()
+            .any(|id| id == destination::DEFAULT_DESTINATION)
+        {
+            User::get_token(database, user::account_id(&user_id, &profile)).await?;
+        }
+        let url = format!("{}{}", telegram::URL_PREFIX, file_id);
+        database
+            .add_request(
+                user_id,
+                chat_id,
+                url,
+                vec![("title", title), ("profile", profile)],
+            )
+            .await?;
         Ok(())
     }
 
@@ -104,3 +506,182 @@ impl Queue {
         Ok(())
     }
 }
+
+// Returns how many seconds to wait before checking again, if the video can't be downloaded yet
+fn live_wait(info: &MediaInfo, recheck: u64) -> Option<u64> {
+    match info.live_status.as_deref() {
+        Some("is_upcoming") => {
+            // Check again right when it's supposed to start, it may still be delayed
+            let starts_in = info
+                .release_timestamp
+                .map(|timestamp| timestamp.saturating_sub(unix_time()))
+                .unwrap_or(0);
+            Some(starts_in.max(recheck))
+        }
+        Some("is_live") | Some("post_live") => Some(recheck),
+        _ if info.is_live == Some(true) => Some(recheck),
+        _ => None,
+    }
+}
+
+// Waiting any longer than this between attempts isn't useful
+const MAX_RETRY_DELAY: u64 = 24 * 3600;
+
+// Doubles with every attempt, e.g. 1, 2, 4 times `base` for the first three
+fn retry_delay(base: u64, attempts: u64) -> u64 {
+    let exponent = attempts.saturating_sub(1).min(u32::MAX as u64) as u32;
+    2u64.checked_pow(exponent)
+        .map(|factor| base.saturating_mul(factor))
+        .unwrap_or(u64::MAX)
+        .min(MAX_RETRY_DELAY)
+}
+
+// Without credentials, or "direct" when no proxy was used
+fn proxy_name(proxy: &Option<String>) -> String {
+    match proxy {
+        Some(proxy) => proxy::redacted(proxy),
+        None => String::from("direct"),
+    }
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+// e.g. "2 hours 5 minutes", only meant for rough waiting times
+pub fn format_duration(seconds: u64) -> String {
+    if seconds >= 2 * 24 * 3600 {
+        return format!("{} days", seconds / (24 * 3600));
+    }
+    let hours = seconds / 3600;
+    let minutes = (seconds % 3600) / 60;
+    match (hours, minutes) {
+        (0, minutes) => plural(minutes.max(1), "minute"),
+        (hours, 0) => plural(hours, "hour"),
+        (hours, minutes) => format!("{} {}", plural(hours, "hour"), plural(minutes, "minute")),
+    }
+}
+
+// e.g. "1 minute" or "2 minutes"
+fn plural(count: u64, unit: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, unit)
+    } else {
+        format!("{} {}s", count, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::FakeDownloader;
+
+    // A fixtures folder for the fake downloader, with one ended video and one upcoming stream
+    async fn fake_downloader(name: &str) -> FakeDownloader {
+        let fixtures_path = std::env::temp_dir().join(format!("queue-test-{}", name));
+        tokio::fs::create_dir_all(&fixtures_path).await.unwrap();
+        tokio::fs::write(fixtures_path.join("video.m4a"), b"not really audio")
+            .await
+            .unwrap();
+        let fixtures = serde_json::json!([
+            {
+                "url": "https://youtu.be/video",
+                "info": { "id": "queue-test-video", "title": "Title", "channel": "Channel", "live_status": "was_live" },
+                "file": "video.m4a",
+            },
+            {
+                "url": "https://youtu.be/upcoming",
+                "info": { "id": "queue-test-upcoming", "title": "Stream", "live_status": "is_upcoming", "release_timestamp": unix_time() + 7200 },
+                "file": "video.m4a",
+            },
+        ]);
+        tokio::fs::write(fixtures_path.join("fixtures.json"), fixtures.to_string())
+            .await
+            .unwrap();
+        let download_path = fixtures_path.join("downloads");
+        tokio::fs::create_dir_all(&download_path).await.unwrap();
+        FakeDownloader::from_dir(&fixtures_path)
+            .unwrap()
+            .with_download_path(&download_path)
+    }
+
+    #[tokio::test]
+    async fn downloads_with_fake_downloader() {
+        let fake = fake_downloader("download").await;
+        let options = DownloadOptions::default();
+        let info = fake
+            .fetch_info("https://youtu.be/video", &options)
+            .await
+            .unwrap();
+        assert_eq!(live_wait(&info, 600), None);
+        let media = downloader::download_media(&fake, "https://youtu.be/video", &info, &options)
+            .await
+            .unwrap();
+        assert_eq!(media.title, "Channel - Title");
+        assert_eq!(
+            media.file_path,
+            std::env::temp_dir().join("queue-test-download/downloads/queue-test-video.m4a")
+        );
+        assert!(media.subtitles_path.is_none());
+        tokio::fs::remove_file(&media.file_path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn waits_for_upcoming_stream() {
+        let fake = fake_downloader("upcoming").await;
+        let info = fake
+            .fetch_info("https://youtu.be/upcoming", &DownloadOptions::default())
+            .await
+            .unwrap();
+        // Checked again when it's supposed to start, but never sooner than the interval
+        let wait = live_wait(&info, 600).unwrap();
+        assert!((7100..=7200).contains(&wait));
+        assert_eq!(live_wait(&info, 10000), Some(10000));
+    }
+
+    #[tokio::test]
+    async fn unknown_url_is_retried() {
+        let fake = fake_downloader("unknown").await;
+        let error = fake
+            .fetch_info("https://youtu.be/unknown", &DownloadOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error.kind, BotErrorKind::DownloadError));
+        assert!(error.kind.is_retryable());
+    }
+
+    #[test]
+    fn parses_request() {
+        let request = Queue::parse_request(" work video https://youtu.be/abc ");
+        assert_eq!(request.video, Some(true));
+        assert_eq!(request.profile, Some("work"));
+        assert_eq!(request.url, "https://youtu.be/abc");
+
+        let request = Queue::parse_request("https://youtu.be/abc");
+        assert_eq!(request.video, None);
+        assert_eq!(request.profile, None);
+        assert_eq!(request.url, "https://youtu.be/abc");
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_a_day() {
+        assert_eq!(retry_delay(60, 1), 60);
+        assert_eq!(retry_delay(60, 3), 240);
+        assert_eq!(retry_delay(60, 100), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u64::MAX, 2), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn formats_duration() {
+        assert_eq!(format_duration(30), "1 minute");
+        assert_eq!(format_duration(600), "10 minutes");
+        assert_eq!(format_duration(3600), "1 hour");
+        assert_eq!(format_duration(3660), "1 hour 1 minute");
+        assert_eq!(format_duration(3900), "1 hour 5 minutes");
+        assert_eq!(format_duration(7500), "2 hours 5 minutes");
+        assert_eq!(format_duration(3 * 24 * 3600), "3 days");
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use crate::{
+    downloader::Chapter,
+    process,
+    types::{BotError, BotErrorKind, BotResult},
+    watchdog::Heartbeat,
+};
+
+// How far before the target cut point to look for a quiet moment
+const SILENCE_WINDOW: f64 = 120.0;
+
+// Parts shorter than this fraction of the maximum are avoided when picking chapter boundaries
+const MIN_PART_FRACTION: f64 = 0.25;
+
+// Splits the file into parts no longer than `max_duration` seconds, preferring chapter
+// boundaries and otherwise silence near the cut point. Returns the original file when it's
+// short enough. Each ffmpeg run is limited to `timeout`. Videos keep their picture.
+pub async fn split_media(
+    file_path: &Path,
+    duration: Option<f64>,
+    chapters: &[Chapter],
+    max_duration: f64,
+    timeout: Duration,
+    heartbeat: Option<&Heartbeat>,
+) -> BotResult<Vec<PathBuf>> {
+    if max_duration <= 0.0 {
+        return Ok(vec![file_path.to_path_buf()]);
+    }
+    let duration = match duration {
+        Some(value) => value,
+        None => probe_duration(file_path, timeout).await?,
+    };
+    if duration <= max_duration {
+        return Ok(vec![file_path.to_path_buf()]);
+    }
+
+    let mut cuts = Vec::new();
+    let mut part_start = 0.0;
+    while duration - part_start > max_duration {
+        let target = part_start + max_duration;
+        let earliest = part_start + max_duration * MIN_PART_FRACTION;
+        let cut = match chapter_cut(chapters, earliest, target) {
+            Some(value) => value,
+            None => find_silence(file_path, target, timeout)
+                .await?
+                .filter(|&silence| silence > earliest)
+                .unwrap_or(target),
+        };
+        cuts.push(cut);
+        part_start = cut;
+    }
+
+    let mut boundaries = vec![0.0];
+    boundaries.extend(cuts);
+    let mut parts = Vec::new();
+    for (index, start) in boundaries.iter().enumerate() {
+        let end = boundaries.get(index + 1).copied();
+        let part_path = part_path(file_path, index + 1);
+        let mut command = Command::new("ffmpeg");
+        command
+            .args(["-y", "-loglevel", "error", "-i"])
+            .arg(file_path)
+            .arg("-ss")
+            .arg(start.to_string());
+        if let Some(end) = end {
+            command.arg("-to").arg(end.to_string());
+        }
+        command
+            .args(["-map", "0:v?", "-map", "0:a", "-c", "copy"])
+            .arg(&part_path);
+        if !process::run(command, timeout, heartbeat)
+            .await?
+            .status
+            .success()
+        {
+            return Err(BotError::new(BotErrorKind::SplitError));
+        }
+        parts.push(part_path);
+    }
+    Ok(parts)
+}
+
+// The last chapter that starts after `earliest` and no later than `target`
+fn chapter_cut(chapters: &[Chapter], earliest: f64, target: f64) -> Option<f64> {
+    chapters
+        .iter()
+        .map(|chapter| chapter.start_time)
+        .filter(|&start| start > earliest && start <= target)
+        .fold(None, |latest: Option<f64>, start| {
+            Some(latest.map_or(start, |latest| latest.max(start)))
+        })
+}
+
+fn part_path(file_path: &Path, number: usize) -> PathBuf {
+    let stem = file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = file_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_default();
+    file_path.with_file_name(format!("{}.part{}.{}", stem, number, extension))
+}
+
+async fn probe_duration(file_path: &Path, timeout: Duration) -> BotResult<f64> {
+    let mut command = Command::new("ffprobe");
+    command
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(file_path);
+    let output = process::run(command, timeout, None).await?;
+    match String::from_utf8(output.stdout)?.trim().parse::<f64>() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(BotError::new(BotErrorKind::SplitError)),
+    }
+}
+
+// Returns the middle of the quiet moment closest to the target, if there is one
+async fn find_silence(file_path: &Path, target: f64, timeout: Duration) -> BotResult<Option<f64>> {
+    let window_start = (target - SILENCE_WINDOW).max(0.0);
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-hide_banner", "-nostats", "-ss"])
+        .arg(window_start.to_string())
+        .arg("-t")
+        .arg((target - window_start).to_string())
+        .arg("-i")
+        .arg(file_path)
+        .args(["-af", "silencedetect=noise=-35dB:d=0.5", "-f", "null", "-"]);
+    let output = process::run(command, timeout, None).await?;
+    let log = String::from_utf8_lossy(&output.stderr);
+    Ok(closest_silence(&log, window_start, target))
+}
+
+// Timestamps are relative to the start of the window, e.g.
+// "[silencedetect @ 0x...] silence_start: 12.5" followed by "silence_end: 13.4 | ..."
+fn closest_silence(log: &str, window_start: f64, target: f64) -> Option<f64> {
+    let mut silences = Vec::new();
+    let mut silence_start = None;
+    for line in log.lines() {
+        if let Some(value) = field_after(line, "silence_start:") {
+            silence_start = Some(value);
+        } else if let Some(end) = field_after(line, "silence_end:") {
+            if let Some(start) = silence_start.take() {
+                silences.push(window_start + (start + end) / 2.0);
+            }
+        }
+    }
+    silences
+        .into_iter()
+        .min_by(|a, b| (target - a).abs().total_cmp(&(target - b).abs()))
+}
+
+fn field_after(line: &str, name: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(name)?;
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapters(starts: &[f64]) -> Vec<Chapter> {
+        starts
+            .iter()
+            .map(|&start_time| Chapter {
+                start_time,
+                title: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn picks_the_last_chapter_before_the_target() {
+        let chapters = chapters(&[0.0, 600.0, 1500.0, 3000.0, 4000.0]);
+        assert_eq!(chapter_cut(&chapters, 900.0, 3600.0), Some(3000.0));
+    }
+
+    #[test]
+    fn ignores_chapters_that_make_short_parts() {
+        let chapters = chapters(&[0.0, 600.0, 4000.0]);
+        assert_eq!(chapter_cut(&chapters, 900.0, 3600.0), None);
+    }
+
+    #[test]
+    fn finds_the_silence_closest_to_the_target() {
+        let log = "\
+[silencedetect @ 0x1] silence_start: 10
+[silencedetect @ 0x1] silence_end: 11 | silence_duration: 1
+[silencedetect @ 0x1] silence_start: 100
+[silencedetect @ 0x1] silence_end: 102 | silence_duration: 2
+";
+        assert_eq!(closest_silence(log, 3480.0, 3600.0), Some(3581.0));
+        assert_eq!(closest_silence("", 3480.0, 3600.0), None);
+    }
+
+    #[test]
+    fn names_parts_after_the_file() {
+        assert_eq!(
+            part_path(Path::new("/tmp/video.m4a"), 2),
+            PathBuf::from("/tmp/video.part2.m4a")
+        );
+    }
+}
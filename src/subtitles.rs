@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+
+use crate::types::{BotError, BotErrorKind, BotResult};
+
+// From: https://docs.rs/once_cell/latest/once_cell/
+// As advised by rust-lang/regex: "Avoid compiling the same regex in a loop"
+macro_rules! regex {
+    ($re:literal $(,)?) => {{
+        static RE: once_cell::sync::OnceCell<regex::Regex> = once_cell::sync::OnceCell::new();
+        RE.get_or_init(|| regex::Regex::new($re).unwrap())
+    }};
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubtitleFormat {
+    Srt,
+    Text,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubtitlePreference {
+    pub language: String,
+    pub format: SubtitleFormat,
+}
+
+impl SubtitlePreference {
+    // Parses user input such as "en", "en srt" or "de txt", defaults to plain text
+    pub fn parse(input: &str) -> BotResult<Self> {
+        let mut parts = input.split_whitespace();
+        let language = match parts.next() {
+            Some(value) => value.to_string(),
+            None => return Err(BotError::new(BotErrorKind::InvalidSettingError)),
+        };
+        let language_regex = regex!(r#"^[a-zA-Z]{2,3}(-[a-zA-Z0-9]{2,8})*$"#);
+        if !language_regex.is_match(&language) {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        let format = match parts.next() {
+            None | Some("txt") | Some("text") => SubtitleFormat::Text,
+            Some("srt") => SubtitleFormat::Srt,
+            Some(_) => return Err(BotError::new(BotErrorKind::InvalidSettingError)),
+        };
+        if parts.next().is_some() {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        Ok(SubtitlePreference { language, format })
+    }
+
+    pub fn extension(&self) -> &str {
+        match self.format {
+            SubtitleFormat::Srt => "srt",
+            SubtitleFormat::Text => "txt",
+        }
+    }
+}
+
+impl std::fmt::Display for SubtitlePreference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.language, self.extension())
+    }
+}
+
+// Converts the downloaded SRT file into the preferred format, returns the path of the result
+pub async fn convert(srt_path: &Path, preference: &SubtitlePreference) -> BotResult<PathBuf> {
+    if preference.format == SubtitleFormat::Srt {
+        return Ok(srt_path.to_path_buf());
+    }
+    let srt = tokio::fs::read_to_string(srt_path).await?;
+    let text_path = srt_path.with_extension("txt");
+    tokio::fs::write(&text_path, srt_to_text(&srt)).await?;
+    tokio::fs::remove_file(srt_path).await?;
+    Ok(text_path)
+}
+
+fn srt_to_text(srt: &str) -> String {
+    let tag_regex = regex!(r#"<[^>]*>"#);
+    let mut lines: Vec<String> = Vec::new();
+    for line in srt.lines() {
+        let line = line.trim();
+        // Skip cue numbers, timings and blank separators
+        if line.is_empty() || line.contains("-->") || line.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let line = tag_regex.replace_all(line, "").trim().to_string();
+        // Auto-generated captions repeat the previous line as the next one scrolls in
+        if line.is_empty() || lines.last() == Some(&line) {
+            continue;
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_srt_to_text() {
+        let srt = "\
+1
+00:00:00,000 --> 00:00:02,500
+<i>Hello</i> and welcome
+
+2
+00:00:02,500 --> 00:00:04,000
+to the <font color=\"#ffffff\">show</font>
+";
+        assert_eq!(srt_to_text(srt), "Hello and welcome\nto the show");
+    }
+
+    #[test]
+    fn drops_rolling_caption_repeats() {
+        // Auto-generated captions show each line twice while the next one scrolls in
+        let srt = "\
+1
+00:00:00,000 --> 00:00:02,000
+so today we're
+
+2
+00:00:02,000 --> 00:00:02,010
+so today we're
+talking about rust
+
+3
+00:00:02,010 --> 00:00:04,000
+talking about rust
+";
+        assert_eq!(srt_to_text(srt), "so today we're\ntalking about rust");
+    }
+
+    #[test]
+    fn handles_crlf_and_digit_only_lines() {
+        let srt = "1\r\n00:00:00,000 --> 00:00:01,000\r\n2023 was a good year\r\n\r\n2\r\n00:00:01,000 --> 00:00:02,000\r\n42\r\n";
+        // A line with only digits can't be told apart from a cue number
+        assert_eq!(srt_to_text(srt), "2023 was a good year");
+    }
+
+    #[test]
+    fn parses_preferences() {
+        let preference = SubtitlePreference::parse("en").unwrap();
+        assert_eq!(preference.language, "en");
+        assert_eq!(preference.format, SubtitleFormat::Text);
+
+        let preference = SubtitlePreference::parse(" pt-BR  srt ").unwrap();
+        assert_eq!(preference.language, "pt-BR");
+        assert_eq!(preference.format, SubtitleFormat::Srt);
+        assert_eq!(preference.to_string(), "pt-BR srt");
+
+        assert_eq!(
+            SubtitlePreference::parse("de text").unwrap().extension(),
+            "txt"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_preferences() {
+        assert!(SubtitlePreference::parse("").is_err());
+        assert!(SubtitlePreference::parse("english").is_err());
+        assert!(SubtitlePreference::parse("en/../x").is_err());
+        assert!(SubtitlePreference::parse("en vtt").is_err());
+        assert!(SubtitlePreference::parse("en srt extra").is_err());
+    }
+}
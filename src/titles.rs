@@ -0,0 +1,259 @@
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    downloader::MediaInfo,
+    types::{BotError, BotErrorKind, BotResult},
+};
+
+// From: https://docs.rs/once_cell/latest/once_cell/
+// As advised by rust-lang/regex: "Avoid compiling the same regex in a loop"
+macro_rules! regex {
+    ($re:literal $(,)?) => {{
+        static RE: once_cell::sync::OnceCell<regex::Regex> = once_cell::sync::OnceCell::new();
+        RE.get_or_init(|| regex::Regex::new($re).unwrap())
+    }};
+}
+
+// Matches yt-dlp's "%(channel)s - %(title)s", which was used before templates existed
+pub const DEFAULT_TEMPLATE: &str = "{channel} - {title}";
+
+pub const PLACEHOLDERS: [&str; 5] = [
+    "channel",
+    "title",
+    "upload_date",
+    "duration",
+    "playlist_index",
+];
+
+// Offered with "/titlefilter common", e.g. "[OFFICIAL VIDEO]", "(Lyrics)", "#shorts" or emoji
+pub const COMMON_FILTERS: [&str; 3] = [
+    r"(?i)\s*[\[(][^\])]*\b(official|video|audio|lyrics?|visuali[sz]er|4k|hd|hq)\b[^\])]*[\])]",
+    r"\s*#[\p{L}\p{N}_]+",
+    r"\s*\p{So}+",
+];
+
+const MAX_TEMPLATE_LENGTH: usize = 200;
+const MAX_PATTERN_LENGTH: usize = 200;
+pub const MAX_FILTERS: usize = 20;
+
+// Keeps user supplied patterns from taking up a lot of memory
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+// A regex replacement applied to every title, an empty replacement removes the match
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TitleFilter {
+    pub pattern: String,
+    #[serde(default)]
+    pub replacement: String,
+}
+
+impl TitleFilter {
+    pub fn new(pattern: &str, replacement: &str) -> BotResult<Self> {
+        if pattern.is_empty() || pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        let filter = TitleFilter {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+        };
+        filter.regex()?;
+        Ok(filter)
+    }
+
+    // Parses user input such as "\(Official Video\)" or "Ep\. (\d+) => Episode $1"
+    pub fn parse(input: &str) -> BotResult<Self> {
+        match input.split_once("=>") {
+            Some((pattern, replacement)) => TitleFilter::new(pattern.trim(), replacement.trim()),
+            None => TitleFilter::new(input.trim(), ""),
+        }
+    }
+
+    fn regex(&self) -> BotResult<Regex> {
+        match RegexBuilder::new(&self.pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()
+        {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::InvalidSettingError)),
+        }
+    }
+}
+
+impl std::fmt::Display for TitleFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.replacement.is_empty() {
+            write!(f, "{}", self.pattern)
+        } else {
+            write!(f, "{} => {}", self.pattern, self.replacement)
+        }
+    }
+}
+
+// How a user wants their uploads named
+#[derive(Clone, Debug)]
+pub struct TitleFormat {
+    pub template: String,
+    pub filters: Vec<TitleFilter>,
+}
+
+impl Default for TitleFormat {
+    fn default() -> Self {
+        TitleFormat {
+            template: DEFAULT_TEMPLATE.to_string(),
+            filters: Vec::new(),
+        }
+    }
+}
+
+impl TitleFormat {
+    // Templates may only contain known placeholders, e.g. "{upload_date} {title}"
+    pub fn validate_template(template: &str) -> BotResult<()> {
+        if template.trim().is_empty() || template.len() > MAX_TEMPLATE_LENGTH {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        let placeholder_regex = regex!(r#"\{([^{}]*)\}"#);
+        for captures in placeholder_regex.captures_iter(template) {
+            if !PLACEHOLDERS.contains(&&captures[1]) {
+                return Err(BotError::new(BotErrorKind::InvalidSettingError));
+            }
+        }
+        // Anything left over is an unclosed or stray brace
+        let remainder = placeholder_regex.replace_all(template, "");
+        if remainder.contains('{') || remainder.contains('}') {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, info: &MediaInfo) -> String {
+        let placeholder_regex = regex!(r#"\{([a-z_]+)\}"#);
+        let rendered =
+            placeholder_regex.replace_all(&self.template, |captures: &regex::Captures| {
+                match &captures[1] {
+                    "channel" => info.channel.clone().unwrap_or_default(),
+                    "title" => info.title.clone(),
+                    "upload_date" => info
+                        .upload_date
+                        .as_deref()
+                        .map(format_date)
+                        .unwrap_or_default(),
+                    "duration" => info.duration.map(format_duration).unwrap_or_default(),
+                    "playlist_index" => info
+                        .playlist_index
+                        .map(|index| index.to_string())
+                        .unwrap_or_default(),
+                    _ => String::new(),
+                }
+            });
+        let mut title = rendered.to_string();
+        for filter in self.filters.iter() {
+            // Saved filters were checked already, a broken one is skipped rather than failing
+            if let Ok(regex) = filter.regex() {
+                title = regex
+                    .replace_all(&title, filter.replacement.as_str())
+                    .to_string();
+            }
+        }
+        let title = tidy(&title);
+        if title.is_empty() {
+            info.title.clone()
+        } else {
+            title
+        }
+    }
+}
+
+// Cleans up after missing values and removed text, e.g. " - Title ()" becomes "Title"
+fn tidy(title: &str) -> String {
+    let empty_brackets_regex = regex!(r#"\(\s*\)|\[\s*\]"#);
+    let title = empty_brackets_regex.replace_all(title, "");
+    let title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+    title
+        .trim_matches(|c: char| c.is_whitespace() || "-|:,·".contains(c))
+        .to_string()
+}
+
+// yt-dlp dates look like "20230304"
+fn format_date(date: &str) -> String {
+    if date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8])
+    } else {
+        date.to_string()
+    }
+}
+
+// e.g. "1:02:03" or "4:05"
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, (seconds % 3600) / 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> MediaInfo {
+        MediaInfo {
+            title: String::from("Episode 12 (Official Video)"),
+            channel: Some(String::from("Channel")),
+            upload_date: Some(String::from("20230304")),
+            duration: Some(3723.4),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn validates_templates() {
+        assert!(TitleFormat::validate_template("{upload_date} {title}").is_ok());
+        assert!(TitleFormat::validate_template(DEFAULT_TEMPLATE).is_ok());
+        assert!(TitleFormat::validate_template("{views} {title}").is_err());
+        assert!(TitleFormat::validate_template("{title").is_err());
+        assert!(TitleFormat::validate_template("title}").is_err());
+        assert!(TitleFormat::validate_template("  ").is_err());
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let format = TitleFormat {
+            template: String::from("{upload_date} {title} [{duration}]"),
+            filters: Vec::new(),
+        };
+        assert_eq!(
+            format.render(&info()),
+            "2023-03-04 Episode 12 (Official Video) [1:02:03]"
+        );
+    }
+
+    #[test]
+    fn applies_filters() {
+        let format = TitleFormat {
+            template: DEFAULT_TEMPLATE.to_string(),
+            filters: vec![
+                TitleFilter::parse(r"\(Official Video\)").unwrap(),
+                TitleFilter::parse(r"Episode (\d+) => Ep. $1").unwrap(),
+            ],
+        };
+        assert_eq!(format.render(&info()), "Channel - Ep. 12");
+    }
+
+    #[test]
+    fn falls_back_to_the_title() {
+        let format = TitleFormat {
+            template: String::from("{playlist_index}"),
+            filters: Vec::new(),
+        };
+        assert_eq!(format.render(&info()), "Episode 12 (Official Video)");
+    }
+
+    #[test]
+    fn tidies_missing_values() {
+        assert_eq!(tidy(" - Title ()"), "Title");
+        assert_eq!(tidy("Channel -  Title [ ]"), "Channel - Title");
+    }
+}
@@ -25,19 +25,92 @@ impl BotError {
 
 #[derive(Clone, Debug)]
 #[non_exhaustive]
+#[allow(clippy::enum_variant_names)]
 pub enum BotErrorKind {
+    AgeRestrictedError,
+    ChecksumError,
+    CopyrightError,
     DownloadError,
     EmptyTokenError,
+    EncryptionError,
+    ExpiredTokenError,
+    FileNotFoundError,
+    FileTooLargeError,
+    ForbiddenError,
+    GeoBlockedError,
+    InvalidCookiesError,
+    InvalidSettingError,
     InvalidTokenError,
     InvalidUrlError,
+    InvalidVersionError,
     IoError,
+    MembersOnlyError,
+    PrivateVideoError,
+    ProcessingError,
+    QuotaExceededError,
+    RateLimitedError,
     RedisError,
+    SmokeTestError,
+    SplitError,
     TelegramError,
+    TimeoutError,
     TypeError,
+    UnauthorizedError,
+    UnknownProfileError,
+    UnsupportedUrlError,
     UploadError,
+    VideoRemovedError,
     WebClientError,
 }
 
+impl BotErrorKind {
+    // Whether trying the same request again later could succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            BotErrorKind::DownloadError
+                | BotErrorKind::IoError
+                | BotErrorKind::RateLimitedError
+                | BotErrorKind::TelegramError
+                | BotErrorKind::TimeoutError
+                | BotErrorKind::WebClientError
+        )
+    }
+
+    // What users are told when a request fails this way
+    pub fn failure_message(&self) -> &'static str {
+        match self {
+            BotErrorKind::PrivateVideoError => "This video is private.",
+            BotErrorKind::VideoRemovedError => "This video is unavailable or has been removed.",
+            BotErrorKind::GeoBlockedError => "This video isn't available in the bot's country.",
+            BotErrorKind::AgeRestrictedError => {
+                "This video is age-restricted. Send a cookies.txt with /cookies to download it."
+            }
+            BotErrorKind::MembersOnlyError => {
+                "This video is for channel members only. Send a cookies.txt from a member account with /cookies."
+            }
+            BotErrorKind::CopyrightError => "This video was taken down due to a copyright claim.",
+            BotErrorKind::RateLimitedError => "YouTube is limiting downloads from the bot right now.",
+            BotErrorKind::UnsupportedUrlError => "This link isn't supported.",
+            BotErrorKind::TimeoutError => "The download took too long.",
+            BotErrorKind::EmptyTokenError => "Please set an /auth token before sending URLs.",
+            BotErrorKind::UploadError => "Unable to upload to Pocket Casts.",
+            BotErrorKind::UnauthorizedError => {
+                "Pocket Casts didn't accept your token, it may have expired. Please set a new one with /auth."
+            }
+            BotErrorKind::ForbiddenError => {
+                "Pocket Casts refused the upload. Uploading files needs a Pocket Casts Plus account."
+            }
+            BotErrorKind::QuotaExceededError => {
+                "There isn't enough space left in your Pocket Casts files storage, see /usage."
+            }
+            BotErrorKind::FileTooLargeError => "This file is too large for Pocket Casts.",
+            BotErrorKind::ProcessingError => "Pocket Casts was unable to process the uploaded file.",
+            _ => "Unable to process request.",
+        }
+    }
+}
+
 impl std::error::Error for BotError {}
 
 impl From<FromUtf8Error> for BotError {
@@ -80,19 +153,53 @@ impl From<teloxide::RequestError> for BotError {
     }
 }
 
-impl Display for BotError {
-    fn fmt(&self, _f: &mut Formatter) -> std::fmt::Result {
-        match self.kind {
-            BotErrorKind::DownloadError => todo!(),
-            BotErrorKind::EmptyTokenError => todo!(),
-            BotErrorKind::InvalidTokenError => todo!(),
-            BotErrorKind::InvalidUrlError => todo!(),
-            BotErrorKind::IoError => todo!(),
-            BotErrorKind::RedisError => todo!(),
-            BotErrorKind::TelegramError => todo!(),
-            BotErrorKind::TypeError => todo!(),
-            BotErrorKind::UploadError => todo!(),
-            BotErrorKind::WebClientError => todo!(),
+impl From<teloxide::DownloadError> for BotError {
+    fn from(_: teloxide::DownloadError) -> BotError {
+        BotError {
+            kind: BotErrorKind::TelegramError,
         }
     }
 }
+
+impl Display for BotError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let message = match self.kind {
+            // Users are told about these when a request fails
+            BotErrorKind::AgeRestrictedError
+            | BotErrorKind::CopyrightError
+            | BotErrorKind::EmptyTokenError
+            | BotErrorKind::FileTooLargeError
+            | BotErrorKind::ForbiddenError
+            | BotErrorKind::GeoBlockedError
+            | BotErrorKind::MembersOnlyError
+            | BotErrorKind::PrivateVideoError
+            | BotErrorKind::ProcessingError
+            | BotErrorKind::QuotaExceededError
+            | BotErrorKind::RateLimitedError
+            | BotErrorKind::TimeoutError
+            | BotErrorKind::UnauthorizedError
+            | BotErrorKind::UnsupportedUrlError
+            | BotErrorKind::UploadError
+            | BotErrorKind::VideoRemovedError => self.kind.failure_message(),
+            BotErrorKind::ChecksumError => "The download doesn't match its checksum.",
+            BotErrorKind::DownloadError => "Unable to download the media.",
+            BotErrorKind::EncryptionError => "Unable to encrypt or decrypt a secret.",
+            BotErrorKind::ExpiredTokenError => "The token has expired.",
+            BotErrorKind::FileNotFoundError => "The file wasn't found.",
+            BotErrorKind::InvalidCookiesError => "The cookies file isn't valid.",
+            BotErrorKind::InvalidSettingError => "The setting isn't valid.",
+            BotErrorKind::InvalidTokenError => "The token isn't valid.",
+            BotErrorKind::InvalidUrlError => "The url isn't valid.",
+            BotErrorKind::InvalidVersionError => "The version isn't valid.",
+            BotErrorKind::IoError => "Unable to read or write a file.",
+            BotErrorKind::RedisError => "Unable to reach the database.",
+            BotErrorKind::SmokeTestError => "The executable didn't pass its smoke test.",
+            BotErrorKind::SplitError => "Unable to split the media.",
+            BotErrorKind::TelegramError => "Unable to reach Telegram.",
+            BotErrorKind::TypeError => "Unable to read a response.",
+            BotErrorKind::UnknownProfileError => "The profile doesn't exist.",
+            BotErrorKind::WebClientError => "Unable to reach a web server.",
+        };
+        write!(f, "{}", message)
+    }
+}
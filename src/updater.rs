@@ -0,0 +1,350 @@
+use std::{
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::{
+    bot::ConfigParameters,
+    crypto,
+    downloader::YtDlpBinary,
+    process::{self, Timeouts},
+    types::{BotError, BotErrorKind, BotResult},
+};
+
+// Name of the executable and checksum file, as published on yt-dlp's GitHub releases
+const BINARY_NAME: &str = "yt-dlp";
+const CHECKSUMS_NAME: &str = "SHA2-256SUMS";
+
+// Symlink in the install path to the build admins switched to last, so it survives restarts
+const ACTIVE_NAME: &str = "yt-dlp-active";
+
+// Downloading a build from the mirror shouldn't take longer than this
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// Switches the yt-dlp executable used by the downloader without restarting the bot.
+//
+// Builds are fetched from `<mirror>/<version>/yt-dlp`, where the mirror is either a url
+// laid out like GitHub releases or a local folder, and checked against `SHA2-256SUMS`.
+pub struct YtDlpUpdater {
+    binary: YtDlpBinary,
+    mirror: String,
+    install_path: PathBuf,
+    smoke_test_file: Option<PathBuf>,
+    timeouts: Timeouts,
+    // Only one update is allowed at a time
+    lock: Mutex<()>,
+}
+
+impl YtDlpUpdater {
+    pub fn new(cfg: &ConfigParameters, binary: YtDlpBinary) -> Self {
+        YtDlpUpdater {
+            binary,
+            mirror: cfg.yt_dlp_mirror.trim_end_matches('/').to_string(),
+            install_path: cfg.yt_dlp_install_path.clone(),
+            smoke_test_file: cfg.yt_dlp_smoke_test_file.clone(),
+            timeouts: Timeouts::new(cfg),
+            lock: Mutex::new(()),
+        }
+    }
+
+    // The build admins switched to last, or whatever yt-dlp is found in $PATH
+    pub fn active_binary(cfg: &ConfigParameters) -> YtDlpBinary {
+        let install_path = &cfg.yt_dlp_install_path;
+        match std::fs::read_link(install_path.join(ACTIVE_NAME)) {
+            Ok(target) if install_path.join(&target).is_file() => {
+                YtDlpBinary::new(install_path.join(target))
+            }
+            _ => YtDlpBinary::default(),
+        }
+    }
+
+    pub async fn installed_version(&self) -> BotResult<String> {
+        version_of(&self.binary.path(), self.timeouts.metadata).await
+    }
+
+    // Installs the requested version and returns the version reported by the new executable
+    pub async fn switch_version(&self, version: &str) -> BotResult<String> {
+        if !is_valid_version(version) {
+            return Err(BotError::new(BotErrorKind::InvalidVersionError));
+        }
+        // Without a fixture, a build that prints its version but can't extract would be accepted
+        if self.smoke_test_file.is_none() {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        let _guard = self.lock.lock().await;
+
+        let binary = self.fetch(&format!("{}/{}", version, BINARY_NAME)).await?;
+        let checksums = self
+            .fetch(&format!("{}/{}", version, CHECKSUMS_NAME))
+            .await?;
+        verify_checksum(&binary, &String::from_utf8(checksums)?)?;
+
+        tokio::fs::create_dir_all(&self.install_path).await?;
+        let new_path = self
+            .install_path
+            .join(format!("{}-{}", BINARY_NAME, version));
+        // The new build is tested under a temporary name, so reinstalling the active
+        // version can't overwrite or delete the executable jobs are currently using
+        let temp_path = self.install_path.join(format!(
+            "{}-{}.tmp-{}",
+            BINARY_NAME,
+            version,
+            crypto::random_token(8)
+        ));
+        let result = self.install(&binary, &temp_path, &new_path).await;
+        if result.is_err() {
+            tokio::fs::remove_file(&temp_path).await.ok();
+        }
+        result
+    }
+
+    // Jobs keep using the current executable until the new one is known to work
+    async fn install(&self, binary: &[u8], temp_path: &Path, new_path: &Path) -> BotResult<String> {
+        tokio::fs::write(temp_path, binary).await?;
+        tokio::fs::set_permissions(temp_path, std::fs::Permissions::from_mode(0o755)).await?;
+        let version = self.smoke_test(temp_path).await?;
+        tokio::fs::rename(temp_path, new_path).await?;
+        self.set_active(new_path).await?;
+        self.binary.replace(new_path.to_path_buf());
+        Ok(version)
+    }
+
+    // Points the active symlink at the new build, swapped in with a rename like the build itself
+    async fn set_active(&self, new_path: &Path) -> BotResult<()> {
+        let file_name = match new_path.file_name() {
+            Some(value) => value,
+            None => return Err(BotError::new(BotErrorKind::IoError)),
+        };
+        let temp_link =
+            self.install_path
+                .join(format!("{}.tmp-{}", ACTIVE_NAME, crypto::random_token(8)));
+        tokio::fs::symlink(file_name, &temp_link).await?;
+        if let Err(error) = tokio::fs::rename(&temp_link, self.install_path.join(ACTIVE_NAME)).await
+        {
+            tokio::fs::remove_file(&temp_link).await.ok();
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, file: &str) -> BotResult<Vec<u8>> {
+        if self.mirror.starts_with("http://") || self.mirror.starts_with("https://") {
+            let response = reqwest::Client::new()
+                .get(format!("{}/{}", self.mirror, file))
+                .timeout(FETCH_TIMEOUT)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                return Err(BotError::new(BotErrorKind::InvalidVersionError));
+            }
+            Ok(response.bytes().await?.to_vec())
+        } else {
+            match tokio::fs::read(Path::new(&self.mirror).join(file)).await {
+                Ok(value) => Ok(value),
+                Err(_) => Err(BotError::new(BotErrorKind::InvalidVersionError)),
+            }
+        }
+    }
+
+    // Makes sure the executable runs and can still extract from the local fixture
+    async fn smoke_test(&self, binary_path: &Path) -> BotResult<String> {
+        let fixture = match &self.smoke_test_file {
+            Some(value) => value,
+            None => return Err(BotError::new(BotErrorKind::SmokeTestError)),
+        };
+        let version = version_of(binary_path, self.timeouts.metadata).await?;
+        let fixture_url = format!("file://{}", fixture.display());
+        let mut command = Command::new(binary_path);
+        command
+            .args(["--enable-file-urls", "--simulate", "--quiet"])
+            .arg(fixture_url);
+        let output = run_new_binary(command, self.timeouts.metadata).await?;
+        if !output.status.success() {
+            return Err(BotError::new(BotErrorKind::SmokeTestError));
+        }
+        Ok(version)
+    }
+}
+
+// Versions are used as part of a path, e.g. "2023.03.04"
+fn is_valid_version(version: &str) -> bool {
+    !version.is_empty()
+        && !version.contains("..")
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+async fn version_of(binary_path: &Path, timeout: Duration) -> BotResult<String> {
+    let mut command = Command::new(binary_path);
+    command.arg("--version");
+    let output = run_new_binary(command, timeout).await?;
+    if !output.status.success() {
+        return Err(BotError::new(BotErrorKind::SmokeTestError));
+    }
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+// A build that hangs fails the smoke test instead of holding the update lock forever
+async fn run_new_binary(command: Command, timeout: Duration) -> BotResult<std::process::Output> {
+    match process::run(command, timeout, None).await {
+        Err(BotError {
+            kind: BotErrorKind::TimeoutError,
+        }) => Err(BotError::new(BotErrorKind::SmokeTestError)),
+        result => result,
+    }
+}
+
+// Checksum files are formatted like `sha256sum` output: "<hex digest>  <file name>"
+fn verify_checksum(binary: &[u8], checksums: &str) -> BotResult<()> {
+    let expected = checksums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let digest = parts.next()?;
+        let name = parts.next()?.trim_start_matches('*');
+        (name == BINARY_NAME).then(|| digest.to_lowercase())
+    });
+    let actual = hex::encode(Sha256::digest(binary));
+    match expected {
+        Some(expected) if expected == actual => Ok(()),
+        _ => Err(BotError::new(BotErrorKind::ChecksumError)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers --version, and reads the fixture only when `works` is true
+    fn fake_build(version: &str, works: bool) -> Vec<u8> {
+        format!(
+            "#!/bin/sh\nif [ \"$1\" = \"--version\" ]; then echo {}; exit 0; fi\nexit {}\n",
+            version,
+            if works { 0 } else { 1 }
+        )
+        .into_bytes()
+    }
+
+    // A local folder mirror with one build, and an empty install path
+    async fn local_updater(
+        name: &str,
+        version: &str,
+        build: &[u8],
+        binary: YtDlpBinary,
+    ) -> YtDlpUpdater {
+        let root = std::env::temp_dir().join(format!("updater-test-{}", name));
+        tokio::fs::remove_dir_all(&root).await.ok();
+        let mirror = root.join("mirror");
+        tokio::fs::create_dir_all(mirror.join(version))
+            .await
+            .unwrap();
+        tokio::fs::write(mirror.join(version).join(BINARY_NAME), build)
+            .await
+            .unwrap();
+        let checksums = format!("{}  {}\n", hex::encode(Sha256::digest(build)), BINARY_NAME);
+        tokio::fs::write(mirror.join(version).join(CHECKSUMS_NAME), checksums)
+            .await
+            .unwrap();
+        YtDlpUpdater {
+            binary,
+            mirror: mirror.display().to_string(),
+            install_path: root.join("install"),
+            smoke_test_file: Some(root.join("fixture.m4a")),
+            timeouts: Timeouts {
+                metadata: Duration::from_secs(10),
+                download: Duration::from_secs(10),
+                postprocess: Duration::from_secs(10),
+            },
+            lock: Mutex::new(()),
+        }
+    }
+
+    #[test]
+    fn verifies_checksums() {
+        let binary = b"yt-dlp build";
+        let digest = hex::encode(Sha256::digest(binary));
+        let checksums = format!("0000  yt-dlp.exe\n{}  yt-dlp\n", digest);
+        assert!(verify_checksum(binary, &checksums).is_ok());
+        assert!(verify_checksum(binary, &format!("{} *yt-dlp", digest.to_uppercase())).is_ok());
+        assert!(verify_checksum(b"another build", &checksums).is_err());
+        assert!(verify_checksum(binary, &format!("{}  yt-dlp.exe", digest)).is_err());
+    }
+
+    #[test]
+    fn validates_versions() {
+        assert!(is_valid_version("2023.03.04"));
+        assert!(is_valid_version("2023.11.16-nightly"));
+        assert!(!is_valid_version(""));
+        assert!(!is_valid_version("../2023.03.04"));
+        assert!(!is_valid_version("2023/03/04"));
+        assert!(!is_valid_version("2023.03.04 --exec"));
+    }
+
+    #[tokio::test]
+    async fn switches_to_a_working_build() {
+        let build = fake_build("2024.01.01", true);
+        let updater = local_updater("works", "2024.01.01", &build, YtDlpBinary::default()).await;
+        assert_eq!(
+            updater.switch_version("2024.01.01").await.unwrap(),
+            "2024.01.01"
+        );
+        let installed = updater.install_path.join("yt-dlp-2024.01.01");
+        assert_eq!(updater.binary.path(), installed);
+        assert_eq!(
+            std::fs::read_link(updater.install_path.join(ACTIVE_NAME)).unwrap(),
+            PathBuf::from("yt-dlp-2024.01.01")
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_smoke_test_keeps_the_previous_build() {
+        let working = fake_build("2024.01.01", true);
+        let updater = local_updater("keeps", "2024.01.01", &working, YtDlpBinary::default()).await;
+        updater.switch_version("2024.01.01").await.unwrap();
+        let previous = updater.binary.path();
+
+        // The same version again, but broken, must not touch the build in use
+        let broken = fake_build("2024.01.01", false);
+        let broken_updater = local_updater(
+            "keeps-broken",
+            "2024.01.01",
+            &broken,
+            updater.binary.clone(),
+        )
+        .await;
+        let broken_updater = YtDlpUpdater {
+            install_path: updater.install_path.clone(),
+            ..broken_updater
+        };
+        let error = broken_updater
+            .switch_version("2024.01.01")
+            .await
+            .unwrap_err();
+        assert!(matches!(error.kind, BotErrorKind::SmokeTestError));
+        assert_eq!(updater.binary.path(), previous);
+        assert_eq!(tokio::fs::read(&previous).await.unwrap(), working);
+        // Only the build and the active link are left, no temporary files
+        let mut entries = std::fs::read_dir(&updater.install_path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        entries.sort();
+        assert_eq!(entries, vec!["yt-dlp-2024.01.01", ACTIVE_NAME]);
+    }
+
+    #[tokio::test]
+    async fn refuses_to_switch_without_a_fixture() {
+        let build = fake_build("2024.01.01", true);
+        let updater = YtDlpUpdater {
+            smoke_test_file: None,
+            ..local_updater("no-fixture", "2024.01.01", &build, YtDlpBinary::default()).await
+        };
+        let error = updater.switch_version("2024.01.01").await.unwrap_err();
+        assert!(matches!(error.kind, BotErrorKind::InvalidSettingError));
+        assert_eq!(updater.binary.path(), PathBuf::from(BINARY_NAME));
+    }
+}
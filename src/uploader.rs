@@ -1,85 +1,979 @@
-use std::{path::PathBuf, time::Duration};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
 
-use once_cell::sync::Lazy;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use futures_util::StreamExt;
 use reqwest::{
-    header::{HeaderMap, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE},
-    Body, Client, Url,
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    Body, Client, Response, StatusCode, Url,
 };
-use serde_json::{json, Value};
+use serde::{Deserialize, Serialize};
 use tokio::fs::{metadata, File};
 use tokio_util::codec::{BytesCodec, FramedRead};
 
-use crate::types::{BotError, BotResult};
-
-pub async fn upload_audio(
-    token: &String,
-    file_title: &String,
-    file_path: &PathBuf,
-) -> BotResult<()> {
-    let file_size = metadata(file_path).await?.len();
-    // Pocket Casts API returns a S3 url to push the audio file to
-    let upload_url = request_upload(token, file_title, file_size).await?;
-    send_file(upload_url, file_path, file_size).await?;
-    Ok(())
-}
-
-async fn request_upload(token: &String, file_name: &String, file_size: u64) -> BotResult<Url> {
-    let url = String::from("https://api.pocketcasts.com/files/upload/request");
-    let mut headers = HeaderMap::new();
-    headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
-    let request_body = json!({
-        "contentType": "audio/mp4",
-        "hasCustomImage": false,
-        "title": file_name,
-        "size": file_size,
-    });
-    // Initializes client once, reuses everytime afterwards. Otherwise reinitializing on every request is slow.
-    let client = Lazy::new(|| {
-        Client::builder()
-            .build()
-            .expect("Client for requesting upload failed to init")
-    });
-    let response = client
-        .post(url)
-        .timeout(Duration::new(5, 0))
-        .headers(headers)
-        .json(&request_body)
-        .send()
-        .await?
-        .text()
-        .await?;
-    let parsed_response: Value = serde_json::from_str(&response).expect("HTTP POST failed");
-    let response_url = parsed_response["url"]
-        .as_str()
-        .expect("Unable to convert url to str");
-    let url = Url::parse(response_url).expect("Unable to parse request URL");
-    Ok(url)
-}
-
-// TODO: Properly deal with Pocketcast errors, such as an invalid auth token or account storage is full.
-async fn send_file(url: Url, file_path: &PathBuf, file_size: u64) -> BotResult<()> {
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, "audio/mp4".parse().unwrap());
-    headers.insert(CONTENT_LENGTH, file_size.to_string().parse().unwrap());
-    let file = File::open(file_path).await?;
-    let stream = FramedRead::new(file, BytesCodec::new());
-    let body = Body::wrap_stream(stream);
-    // Initializes client once, reuses everytime afterwards. Otherwise reinitializing on every request is slow.
-    let client = Lazy::new(|| {
-        Client::builder()
-            .build()
-            .expect("Client for sending file failed to init")
-    });
-    let response = client
-        .put(url)
-        .timeout(Duration::new(5, 0))
-        .headers(headers)
-        .body(body)
-        .send()
-        .await?;
-    if response.status().is_success() {
-        return Ok(());
+use crate::{
+    bot::ConfigParameters,
+    types::{BotError, BotErrorKind, BotResult},
+    watchdog::Heartbeat,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadRequest<'a> {
+    content_type: &'a str,
+    has_custom_image: bool,
+    title: &'a str,
+    size: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadResponse {
+    // Presigned S3 url to push the file to
+    url: String,
+    #[serde(alias = "uuid")]
+    file_uuid: String,
+}
+
+// Sent when the files storage is full
+const QUOTA_ERROR_ID: &str = "files_storage_limit_exceeded";
+
+// Pocket Casts puts the reason for a failed request in the body, e.g.
+// {"errorMessage": "...", "errorMessageId": "files_storage_limit_exceeded"}
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorResponse {
+    #[serde(default)]
+    error_message: Option<String>,
+    #[serde(default)]
+    error_message_id: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginRequest<'a> {
+    email: &'a str,
+    password: &'a str,
+    scope: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RefreshRequest<'a> {
+    grant_type: &'a str,
+    refresh_token: &'a str,
+    scope: &'a str,
+}
+
+// Returned when logging in or refreshing the access token
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub access_token: String,
+    // Only sent on login for some accounts, the old one stays valid otherwise
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    // Seconds until the access token expires
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+// The parts of an access token's payload the bot cares about
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TokenClaims {
+    // Unix time the token stops working
+    #[serde(default)]
+    pub exp: Option<u64>,
+    // The account's uuid
+    #[serde(default)]
+    pub sub: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+// Reads the claims without checking the signature, Pocket Casts does that when it's used
+pub fn decode_token(token: &str) -> BotResult<TokenClaims> {
+    let payload = match token.split('.').nth(1) {
+        Some(value) => value,
+        None => return Err(BotError::new(BotErrorKind::InvalidTokenError)),
+    };
+    let payload = match URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')) {
+        Ok(value) => value,
+        Err(_) => return Err(BotError::new(BotErrorKind::InvalidTokenError)),
+    };
+    match serde_json::from_slice(&payload) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(BotError::new(BotErrorKind::InvalidTokenError)),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesUsage {
+    // In bytes
+    pub total_size: u64,
+    pub used_size: u64,
+    #[serde(default)]
+    pub total_files: u64,
+}
+
+impl FilesUsage {
+    pub fn remaining_size(&self) -> u64 {
+        self.total_size.saturating_sub(self.used_size)
+    }
+}
+
+// A file in the user's Pocket Casts files section
+#[derive(Clone, Debug)]
+pub struct UploadedFile {
+    pub uuid: String,
+    pub title: String,
+}
+
+// A file as listed by Pocket Casts
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PocketCastsFile {
+    pub uuid: String,
+    #[serde(default)]
+    pub title: String,
+    // In bytes
+    #[serde(default)]
+    pub size: Option<u64>,
+    // In seconds
+    #[serde(default)]
+    pub duration: Option<f64>,
+    // 0 for none, otherwise one of the app's colors
+    #[serde(default)]
+    pub colour: u8,
+    // When it was uploaded, e.g. "2023-03-04T12:34:56Z"
+    #[serde(default)]
+    pub published: Option<String>,
+    // 3 once it's been played to the end
+    #[serde(default)]
+    pub playing_status: Option<u8>,
+    // In seconds
+    #[serde(default)]
+    pub played_up_to: Option<f64>,
+    #[serde(default)]
+    pub starred: bool,
+}
+
+impl PocketCastsFile {
+    pub fn is_played(&self) -> bool {
+        if self.playing_status == Some(3) {
+            return true;
+        }
+        // Some apps stop just short of the end without marking it as played
+        match (self.played_up_to, self.duration) {
+            (Some(played), Some(duration)) if duration > 0.0 => played / duration >= 0.95,
+            _ => false,
+        }
+    }
+}
+
+// Where Pocket Casts is at with a file after it was pushed to S3
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UploadStatus {
+    Processing,
+    Ready,
+    Failed,
+}
+
+#[derive(Deserialize)]
+struct UploadStatusResponse {
+    #[serde(default)]
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct FilesResponse {
+    #[serde(default)]
+    files: Vec<PocketCastsFile>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileUpdate<'a> {
+    uuid: &'a str,
+    title: &'a str,
+    colour: u8,
+}
+
+#[derive(Serialize)]
+struct UpdateRequest<'a> {
+    files: Vec<FileUpdate<'a>>,
+}
+
+// Pocket Casts treats the files section as a podcast with this uuid
+const USER_FILES_PODCAST_UUID: &str = "da7aba5e-f11e-f11e-f11e-da7aba5ef11e";
+
+// Where in Up Next a new file goes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpNextPosition {
+    Top,
+    Bottom,
+}
+
+impl UpNextPosition {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "top" => Some(UpNextPosition::Top),
+            "bottom" => Some(UpNextPosition::Bottom),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UpNextPosition::Top => "top",
+            UpNextPosition::Bottom => "bottom",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct UpNextEpisode<'a> {
+    uuid: &'a str,
+    title: &'a str,
+    podcast: &'a str,
+}
+
+#[derive(Serialize)]
+struct UpNextRequest<'a> {
+    version: u8,
+    episode: UpNextEpisode<'a>,
+}
+
+// Retrying a push any later than this isn't useful
+const MAX_UPLOAD_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone)]
+pub struct PocketCastsClient {
+    client: Client,
+    base_url: String,
+    // For API calls, pushing the file itself gets longer depending on its size
+    api_timeout: Duration,
+    upload_min_timeout: Duration,
+    // Bytes per second, the slowest connection an upload is given time for
+    upload_min_speed: u64,
+    upload_attempts: u64,
+    upload_retry_delay: Duration,
+}
+
+// Why pushing a file to S3 failed
+enum SendFailure {
+    // The presigned url is only valid for a while, a new one is needed
+    Expired,
+    // Network errors, timeouts and server errors may go away on their own
+    Retryable(BotError),
+    Fatal(BotError),
+}
+
+impl PocketCastsClient {
+    pub fn new(cfg: &ConfigParameters) -> Self {
+        PocketCastsClient {
+            client: Client::new(),
+            base_url: cfg.pocket_casts_api_url.trim_end_matches('/').to_string(),
+            api_timeout: Duration::from_secs(cfg.pocket_casts_api_timeout),
+            upload_min_timeout: Duration::from_secs(cfg.upload_min_timeout),
+            upload_min_speed: cfg.upload_min_speed.max(1),
+            upload_attempts: cfg.upload_attempts.max(1),
+            upload_retry_delay: Duration::from_secs(cfg.upload_retry_delay),
+        }
+    }
+
+    pub async fn login(&self, email: &str, password: &str) -> BotResult<Session> {
+        let request_body = LoginRequest {
+            email,
+            password,
+            scope: "webplayer",
+        };
+        let response = self
+            .client
+            .post(format!("{}/user/login_pocket_casts", self.base_url))
+            .timeout(self.api_timeout)
+            .json(&request_body)
+            .send()
+            .await?;
+        // Wrong credentials come back as a bad request
+        if response.status() == StatusCode::BAD_REQUEST {
+            return Err(BotError::new(BotErrorKind::UnauthorizedError));
+        }
+        parse_session(check_response(response).await?).await
+    }
+
+    pub async fn refresh(&self, refresh_token: &str) -> BotResult<Session> {
+        let request_body = RefreshRequest {
+            grant_type: "refresh_token",
+            refresh_token,
+            scope: "webplayer",
+        };
+        let response = self
+            .client
+            .post(format!("{}/user/token", self.base_url))
+            .timeout(self.api_timeout)
+            .json(&request_body)
+            .send()
+            .await?;
+        // A refresh token that's been revoked is a bad request too
+        if response.status() == StatusCode::BAD_REQUEST {
+            return Err(BotError::new(BotErrorKind::UnauthorizedError));
+        }
+        parse_session(check_response(response).await?).await
+    }
+
+    // Makes a cheap authenticated request to see if Pocket Casts accepts the token
+    pub async fn check_token(&self, token: &str) -> BotResult<()> {
+        let response = self
+            .client
+            .post(format!("{}/subscription/status", self.base_url))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .json(&serde_json::json!({}))
+            .send()
+            .await?;
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn usage(&self, token: &str) -> BotResult<FilesUsage> {
+        let response = self
+            .client
+            .get(format!("{}/files/usage", self.base_url))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .send()
+            .await?;
+        let response = check_response(response).await?;
+        match response.json::<FilesUsage>().await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::TypeError)),
+        }
+    }
+
+    pub async fn upload_status(&self, token: &str, uuid: &str) -> BotResult<UploadStatus> {
+        let response = self
+            .client
+            .get(format!("{}/files/upload/status/{}", self.base_url, uuid))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(BotError::new(BotErrorKind::FileNotFoundError));
+        }
+        let response = check_response(response).await?;
+        let status = match response.json::<UploadStatusResponse>().await {
+            Ok(value) => value.status.to_lowercase(),
+            Err(_) => return Err(BotError::new(BotErrorKind::TypeError)),
+        };
+        Ok(match status.as_str() {
+            "ready" | "uploaded" | "complete" | "completed" => UploadStatus::Ready,
+            "failed" | "error" => UploadStatus::Failed,
+            _ => UploadStatus::Processing,
+        })
+    }
+
+    // Polls until Pocket Casts has processed the file. Returns false if it's still processing
+    // once `timeout` is up, and a ProcessingError if processing failed.
+    pub async fn wait_until_processed(
+        &self,
+        token: &str,
+        uuid: &str,
+        timeout: Duration,
+        poll_interval: Duration,
+        heartbeat: Option<&Heartbeat>,
+    ) -> BotResult<bool> {
+        let started = Instant::now();
+        loop {
+            // The file may not be known yet right after the upload, and a failed check is
+            // tried again rather than failing a request that was already uploaded
+            match self.upload_status(token, uuid).await {
+                Ok(UploadStatus::Ready) => return Ok(true),
+                Ok(UploadStatus::Failed) => {
+                    return Err(BotError::new(BotErrorKind::ProcessingError))
+                }
+                Ok(UploadStatus::Processing) => (),
+                Err(error)
+                    if matches!(
+                        error.kind,
+                        BotErrorKind::FileNotFoundError | BotErrorKind::WebClientError
+                    ) => {}
+                Err(error) => return Err(error),
+            }
+            if let Some(heartbeat) = heartbeat {
+                heartbeat.beat();
+            }
+            if started.elapsed() + poll_interval > timeout {
+                return Ok(false);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    pub async fn list_files(&self, token: &str) -> BotResult<Vec<PocketCastsFile>> {
+        let response = self
+            .client
+            .get(format!("{}/files", self.base_url))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .send()
+            .await?;
+        let response = check_response(response).await?;
+        match response.json::<FilesResponse>().await {
+            Ok(value) => Ok(value.files),
+            Err(_) => Err(BotError::new(BotErrorKind::TypeError)),
+        }
+    }
+
+    pub async fn get_file(&self, token: &str, uuid: &str) -> BotResult<PocketCastsFile> {
+        let files = self.list_files(token).await?;
+        match files.into_iter().find(|file| file.uuid == uuid) {
+            Some(file) => Ok(file),
+            None => Err(BotError::new(BotErrorKind::FileNotFoundError)),
+        }
+    }
+
+    // Saves the file's title and colour
+    pub async fn update_file(&self, token: &str, file: &PocketCastsFile) -> BotResult<()> {
+        let request_body = UpdateRequest {
+            files: vec![FileUpdate {
+                uuid: &file.uuid,
+                title: &file.title,
+                colour: file.colour,
+            }],
+        };
+        let response = self
+            .client
+            .post(format!("{}/files", self.base_url))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .json(&request_body)
+            .send()
+            .await?;
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn delete_file(&self, token: &str, uuid: &str) -> BotResult<()> {
+        let response = self
+            .client
+            .delete(format!("{}/files/{}", self.base_url, uuid))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(BotError::new(BotErrorKind::FileNotFoundError));
+        }
+        check_response(response).await?;
+        Ok(())
+    }
+
+    pub async fn add_to_up_next(
+        &self,
+        token: &str,
+        file: &UploadedFile,
+        position: UpNextPosition,
+    ) -> BotResult<()> {
+        let path = match position {
+            UpNextPosition::Top => "play_next",
+            UpNextPosition::Bottom => "play_last",
+        };
+        let request_body = UpNextRequest {
+            version: 2,
+            episode: UpNextEpisode {
+                uuid: &file.uuid,
+                title: &file.title,
+                podcast: USER_FILES_PODCAST_UUID,
+            },
+        };
+        let response = self
+            .client
+            .post(format!("{}/up_next/{}", self.base_url, path))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .json(&request_body)
+            .send()
+            .await?;
+        check_response(response).await?;
+        Ok(())
+    }
+
+    // Retries failed pushes with a growing delay, asking for a new url when the old one expired
+    pub async fn upload_media(
+        &self,
+        token: &str,
+        file_title: &str,
+        file_path: &Path,
+        heartbeat: Option<&Heartbeat>,
+    ) -> BotResult<UploadedFile> {
+        let file_size = metadata(file_path).await?.len();
+        let content_type = content_type(file_path);
+        // Pocket Casts API returns a S3 url to push the file to
+        let mut upload = self
+            .request_upload(token, file_title, file_size, content_type)
+            .await?;
+        let mut attempt = 1;
+        loop {
+            let upload_url = match Url::parse(&upload.url) {
+                Ok(value) => value,
+                Err(_) => return Err(BotError::new(BotErrorKind::UploadError)),
+            };
+            let failure = match self
+                .send_file(upload_url, file_path, file_size, content_type, heartbeat)
+                .await
+            {
+                Ok(_) => {
+                    return Ok(UploadedFile {
+                        uuid: upload.file_uuid,
+                        title: file_title.to_string(),
+                    })
+                }
+                Err(failure) => failure,
+            };
+            let (error, expired) = match failure {
+                SendFailure::Fatal(error) => return Err(error),
+                SendFailure::Retryable(error) => (error, false),
+                SendFailure::Expired => (BotError::new(BotErrorKind::UploadError), true),
+            };
+            if attempt >= self.upload_attempts {
+                self.delete_file(token, &upload.file_uuid).await.ok();
+                return Err(error);
+            }
+            if expired {
+                // The old file never got its contents, so it's replaced rather than kept around
+                self.delete_file(token, &upload.file_uuid).await.ok();
+                upload = self
+                    .request_upload(token, file_title, file_size, content_type)
+                    .await?;
+            } else {
+                tokio::time::sleep(self.retry_delay(attempt)).await;
+            }
+            attempt += 1;
+        }
+    }
+
+    async fn request_upload(
+        &self,
+        token: &str,
+        file_name: &str,
+        file_size: u64,
+        content_type: &str,
+    ) -> BotResult<UploadResponse> {
+        let request_body = UploadRequest {
+            content_type,
+            has_custom_image: false,
+            title: file_name,
+            size: file_size,
+        };
+        let response = self
+            .client
+            .post(format!("{}/files/upload/request", self.base_url))
+            .timeout(self.api_timeout)
+            .bearer_auth(token)
+            .json(&request_body)
+            .send()
+            .await?;
+        let response = check_response(response).await?;
+        match response.json::<UploadResponse>().await {
+            Ok(value) => Ok(value),
+            Err(_) => Err(BotError::new(BotErrorKind::UploadError)),
+        }
+    }
+
+    async fn send_file(
+        &self,
+        url: Url,
+        file_path: &Path,
+        file_size: u64,
+        content_type: &str,
+        heartbeat: Option<&Heartbeat>,
+    ) -> Result<(), SendFailure> {
+        let file = match File::open(file_path).await {
+            Ok(value) => value,
+            Err(error) => return Err(SendFailure::Fatal(error.into())),
+        };
+        // Every chunk sent counts as progress, large uploads take a while
+        let heartbeat = heartbeat.cloned();
+        let stream = FramedRead::new(file, BytesCodec::new()).inspect(move |_| {
+            if let Some(heartbeat) = &heartbeat {
+                heartbeat.beat();
+            }
+        });
+        let body = Body::wrap_stream(stream);
+        let response = self
+            .client
+            .put(url)
+            .timeout(self.upload_timeout(file_size))
+            .header(CONTENT_TYPE, content_type)
+            .header(CONTENT_LENGTH, file_size)
+            .body(body)
+            .send()
+            .await;
+        let response = match response {
+            Ok(value) => value,
+            Err(error) => return Err(SendFailure::Retryable(error.into())),
+        };
+        // S3 answers with XML instead of Pocket Casts errors, e.g.
+        // "<Code>AccessDenied</Code><Message>Request has expired</Message>"
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body = response.text().await.unwrap_or_default().to_lowercase();
+        match status {
+            StatusCode::FORBIDDEN if body.contains("expired") => Err(SendFailure::Expired),
+            StatusCode::PAYLOAD_TOO_LARGE => Err(SendFailure::Fatal(BotError::new(
+                BotErrorKind::FileTooLargeError,
+            ))),
+            StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => Err(
+                SendFailure::Retryable(BotError::new(BotErrorKind::WebClientError)),
+            ),
+            status if status.is_server_error() => Err(SendFailure::Retryable(BotError::new(
+                BotErrorKind::WebClientError,
+            ))),
+            _ => Err(SendFailure::Fatal(BotError::new(BotErrorKind::UploadError))),
+        }
+    }
+
+    // Doubles after every attempt, up to MAX_UPLOAD_RETRY_DELAY
+    fn retry_delay(&self, attempt: u64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(u32::MAX as u64) as u32;
+        let factor = 2u32.checked_pow(exponent).unwrap_or(u32::MAX);
+        self.upload_retry_delay
+            .saturating_mul(factor)
+            .min(MAX_UPLOAD_RETRY_DELAY)
+    }
+
+    // Enough time to push the file at the slowest expected speed
+    fn upload_timeout(&self, file_size: u64) -> Duration {
+        self.upload_min_timeout + Duration::from_secs(file_size / self.upload_min_speed)
+    }
+}
+
+async fn parse_session(response: Response) -> BotResult<Session> {
+    match response.json::<Session>().await {
+        Ok(value) => Ok(value),
+        Err(_) => Err(BotError::new(BotErrorKind::TypeError)),
+    }
+}
+
+// Turns unsuccessful Pocket Casts responses into the matching error
+async fn check_response(response: Response) -> BotResult<Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let error = response.json::<ErrorResponse>().await.unwrap_or_default();
+    // The message is only meant for people, so it's logged rather than matched on
+    if let Some(message) = &error.error_message {
+        println!("Pocket Casts answered {}: {}", status, message);
+    }
+    Err(BotError::new(error_kind(status, &error)))
+}
+
+// The status code for a full account isn't documented, so the error id is checked first
+fn error_kind(status: StatusCode, error: &ErrorResponse) -> BotErrorKind {
+    if error.error_message_id.as_deref() == Some(QUOTA_ERROR_ID) {
+        return BotErrorKind::QuotaExceededError;
+    }
+    match status {
+        StatusCode::UNAUTHORIZED => BotErrorKind::UnauthorizedError,
+        StatusCode::FORBIDDEN => BotErrorKind::ForbiddenError,
+        StatusCode::PAYLOAD_TOO_LARGE => BotErrorKind::FileTooLargeError,
+        _ => BotErrorKind::UploadError,
+    }
+}
+
+// e.g. "1.5 GB" or "120.0 MB"
+pub fn format_size(bytes: u64) -> String {
+    let units = ["bytes", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
     } else {
-        return Err(BotError::new(crate::types::BotErrorKind::UploadError));
+        format!("{:.1} {}", size, units[unit])
+    }
+}
+
+// Downloads are always either M4A audio or MP4 video
+pub fn content_type(file_path: &Path) -> &'static str {
+    match file_path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("mp4") => "video/mp4",
+        _ => "audio/mp4",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        net::SocketAddr,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+
+    use warp::{http::StatusCode as WarpStatus, Filter, Reply};
+
+    use super::*;
+
+    // How the stand-in answers the next push
+    #[derive(Clone, Copy)]
+    enum Push {
+        Ok,
+        ServerError,
+        Expired,
+        Slow(Duration),
+    }
+
+    #[derive(Default)]
+    struct StandIn {
+        pushes: VecDeque<Push>,
+        upload_requests: u32,
+        push_count: u32,
+        deleted: Vec<String>,
+    }
+
+    // A local server answering like the Pocket Casts API and S3 do
+    async fn start_stand_in(pushes: Vec<Push>) -> (SocketAddr, Arc<Mutex<StandIn>>) {
+        let state = Arc::new(Mutex::new(StandIn {
+            pushes: pushes.into(),
+            ..Default::default()
+        }));
+        let addr = Arc::new(Mutex::new(None::<SocketAddr>));
+
+        let request_state = state.clone();
+        let request_addr = addr.clone();
+        let upload_request = warp::post()
+            .and(warp::path!("files" / "upload" / "request"))
+            .map(move || {
+                let mut state = request_state.lock().unwrap();
+                state.upload_requests += 1;
+                let number = state.upload_requests;
+                let addr = request_addr.lock().unwrap().unwrap();
+                warp::reply::json(&serde_json::json!({
+                    "url": format!("http://{}/push/{}", addr, number),
+                    "fileUuid": format!("file-{}", number),
+                }))
+                .into_response()
+            });
+        let push_state = state.clone();
+        let push = warp::put()
+            .and(warp::path!("push" / u32))
+            .and(warp::body::bytes())
+            .and_then(move |_number: u32, _body: warp::hyper::body::Bytes| {
+                let state = push_state.clone();
+                async move {
+                    let push = {
+                        let mut state = state.lock().unwrap();
+                        state.push_count += 1;
+                        state.pushes.pop_front().unwrap_or(Push::Ok)
+                    };
+                    let response = match push {
+                        Push::Ok => WarpStatus::OK.into_response(),
+                        Push::ServerError => WarpStatus::INTERNAL_SERVER_ERROR.into_response(),
+                        Push::Expired => warp::reply::with_status(
+                            "<Error><Code>AccessDenied</Code><Message>Request has expired</Message></Error>",
+                            WarpStatus::FORBIDDEN,
+                        )
+                        .into_response(),
+                        Push::Slow(delay) => {
+                            tokio::time::sleep(delay).await;
+                            WarpStatus::OK.into_response()
+                        }
+                    };
+                    Ok::<_, std::convert::Infallible>(response)
+                }
+            });
+        let delete_state = state.clone();
+        let delete = warp::delete()
+            .and(warp::path!("files" / String))
+            .map(move |uuid: String| {
+                delete_state.lock().unwrap().deleted.push(uuid);
+                warp::reply::json(&serde_json::json!({})).into_response()
+            });
+
+        let (bound, server) =
+            warp::serve(upload_request.or(push).or(delete)).bind_ephemeral(([127, 0, 0, 1], 0));
+        *addr.lock().unwrap() = Some(bound);
+        tokio::spawn(server);
+        (bound, state)
+    }
+
+    fn client(addr: SocketAddr, upload_attempts: u64) -> PocketCastsClient {
+        PocketCastsClient {
+            client: Client::new(),
+            base_url: format!("http://{}", addr),
+            api_timeout: Duration::from_secs(5),
+            upload_min_timeout: Duration::from_millis(500),
+            upload_min_speed: u64::MAX,
+            upload_attempts,
+            upload_retry_delay: Duration::ZERO,
+        }
+    }
+
+    async fn media_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("uploader-test-{}.m4a", name));
+        tokio::fs::write(&path, b"not really audio").await.unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn retries_after_server_error() {
+        let (addr, state) = start_stand_in(vec![Push::ServerError, Push::Ok]).await;
+        let path = media_file("server-error").await;
+        let file = client(addr, 3)
+            .upload_media("token", "Title", &path, None)
+            .await
+            .unwrap();
+        let state = state.lock().unwrap();
+        assert_eq!(file.uuid, "file-1");
+        assert_eq!(state.push_count, 2);
+        assert_eq!(state.upload_requests, 1);
+        assert!(state.deleted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn requests_new_url_when_expired() {
+        let (addr, state) = start_stand_in(vec![Push::Expired, Push::Ok]).await;
+        let path = media_file("expired").await;
+        let file = client(addr, 3)
+            .upload_media("token", "Title", &path, None)
+            .await
+            .unwrap();
+        let state = state.lock().unwrap();
+        assert_eq!(file.uuid, "file-2");
+        assert_eq!(state.upload_requests, 2);
+        assert_eq!(state.deleted, vec![String::from("file-1")]);
+    }
+
+    #[tokio::test]
+    async fn times_out_slow_push() {
+        let slow = Push::Slow(Duration::from_secs(3));
+        let (addr, state) = start_stand_in(vec![slow, Push::Ok]).await;
+        let path = media_file("slow").await;
+        let result = client(addr, 1)
+            .upload_media("token", "Title", &path, None)
+            .await;
+        assert!(matches!(
+            result.map_err(|error| error.kind),
+            Err(BotErrorKind::WebClientError)
+        ));
+        assert_eq!(state.lock().unwrap().deleted, vec![String::from("file-1")]);
+
+        // Given another attempt, the second push goes through
+        let (addr, state) = start_stand_in(vec![slow, Push::Ok]).await;
+        let file = client(addr, 2)
+            .upload_media("token", "Title", &path, None)
+            .await
+            .unwrap();
+        assert_eq!(file.uuid, "file-1");
+        assert_eq!(state.lock().unwrap().push_count, 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_upload_attempts() {
+        let (addr, state) = start_stand_in(vec![Push::ServerError; 5]).await;
+        let path = media_file("attempts").await;
+        let result = client(addr, 3)
+            .upload_media("token", "Title", &path, None)
+            .await;
+        assert!(result.is_err());
+        let state = state.lock().unwrap();
+        assert_eq!(state.push_count, 3);
+        assert_eq!(state.deleted, vec![String::from("file-1")]);
+    }
+
+    #[tokio::test]
+    async fn works_with_mock_server() {
+        let addr = crate::mock_pocket_casts::start_for_tests("upload:server_error:1").await;
+        let client = client(addr, 2);
+        let session = client.login("user@example.com", "password").await.unwrap();
+        let token = session.access_token.as_str();
+        client.check_token(token).await.unwrap();
+        assert_eq!(client.usage(token).await.unwrap().used_size, 0);
+
+        let path = media_file("mock").await;
+        let file = client
+            .upload_media(token, "Title", &path, None)
+            .await
+            .unwrap();
+        let processed = client
+            .wait_until_processed(
+                token,
+                &file.uuid,
+                Duration::from_secs(5),
+                Duration::from_millis(10),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(processed);
+        let files = client.list_files(token).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].uuid, file.uuid);
+        assert_eq!(files[0].title, "Title");
+
+        client.delete_file(token, &file.uuid).await.unwrap();
+        assert!(client.list_files(token).await.unwrap().is_empty());
+        let refreshed = client
+            .refresh(session.refresh_token.as_deref().unwrap())
+            .await
+            .unwrap();
+        client.check_token(&refreshed.access_token).await.unwrap();
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        let mut client = client(([127, 0, 0, 1], 0).into(), 100);
+        client.upload_retry_delay = Duration::from_secs(5);
+        assert_eq!(client.retry_delay(1), Duration::from_secs(5));
+        assert_eq!(client.retry_delay(3), Duration::from_secs(20));
+        assert_eq!(client.retry_delay(64), MAX_UPLOAD_RETRY_DELAY);
+    }
+
+    fn error(id: Option<&str>, message: &str) -> ErrorResponse {
+        ErrorResponse {
+            error_message: Some(message.to_string()),
+            error_message_id: id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn full_storage_is_reported_as_quota() {
+        let full = error(Some(QUOTA_ERROR_ID), "Your files storage is full");
+        assert!(matches!(
+            error_kind(StatusCode::BAD_REQUEST, &full),
+            BotErrorKind::QuotaExceededError
+        ));
+    }
+
+    #[test]
+    fn other_client_errors_are_not_quota() {
+        let invalid = error(Some("invalid_title"), "No space allowed in title");
+        assert!(matches!(
+            error_kind(StatusCode::BAD_REQUEST, &invalid),
+            BotErrorKind::UploadError
+        ));
+        let limited = error(None, "Request limit exceeded");
+        assert!(matches!(
+            error_kind(StatusCode::FORBIDDEN, &limited),
+            BotErrorKind::ForbiddenError
+        ));
+        assert!(matches!(
+            error_kind(StatusCode::UNAUTHORIZED, &ErrorResponse::default()),
+            BotErrorKind::UnauthorizedError
+        ));
     }
 }
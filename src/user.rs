@@ -1,8 +1,19 @@
+use serde::{de::DeserializeOwned, Serialize};
+
 use crate::{
+    crypto,
     database::Database,
+    destination,
+    queue::unix_time,
+    subtitles::SubtitlePreference,
+    titles::{TitleFilter, TitleFormat, MAX_FILTERS},
     types::{BotError, BotErrorKind, BotResult},
+    uploader::{self, PocketCastsClient, Session, TokenClaims, UpNextPosition},
 };
 
+// Seconds before an access token expires that it's refreshed
+const TOKEN_REFRESH_MARGIN: u64 = 10 * 60;
+
 // From: https://docs.rs/once_cell/latest/once_cell/
 // As advised by rust-lang/regex: "Avoid compiling the same regex in a loop"
 macro_rules! regex {
@@ -12,29 +23,493 @@ macro_rules! regex {
     }};
 }
 
+// Profiles let one Telegram user keep several Pocket Casts accounts, e.g. "work" and
+// "personal". The default profile is stored under the plain user id, as before profiles.
+pub const DEFAULT_PROFILE: &str = "default";
+
+// Tokens of named profiles are stored under "<user id>:<profile>"
+pub fn account_id(user_id: &str, profile: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        user_id.to_string()
+    } else {
+        format!("{}:{}", user_id, profile)
+    }
+}
+
 pub struct User {}
 
 impl User {
     // TODO: Check if token already exists and return an "Update success" message
+    // The token is only saved once Pocket Casts accepts it. Returns what's known about it.
     pub async fn set_token(
         database: &mut Database,
+        pocket_casts: &PocketCastsClient,
         user_id: String,
+        profile: &str,
         token: String,
-    ) -> BotResult<()> {
+    ) -> BotResult<TokenClaims> {
+        let token = token.trim().to_string();
         if token.is_empty() {
             return Err(BotError::new(BotErrorKind::EmptyTokenError));
         }
-        let jwt_regex = regex!(r#"^([a-zA-Z0-9_=]+)\.([a-zA-Z0-9_=]+)\.([a-zA-Z0-9_\-\+/=]*)"#);
+        let jwt_regex = regex!(r#"^([a-zA-Z0-9_\-=]+)\.([a-zA-Z0-9_\-=]+)\.([a-zA-Z0-9_\-\+/=]*)"#);
         if !jwt_regex.is_match(&token) {
             return Err(BotError::new(BotErrorKind::InvalidTokenError));
         }
-        database.set_token(user_id, token).await?;
+        let claims = uploader::decode_token(&token)?;
+        if claims.exp.map(|exp| exp <= unix_time()).unwrap_or(false) {
+            return Err(BotError::new(BotErrorKind::ExpiredTokenError));
+        }
+        pocket_casts.check_token(&token).await?;
+        let account = account_id(&user_id, profile);
+        database.set_token(account.clone(), token).await?;
+        User::add_profile(database, user_id, profile).await?;
+        // A token set by hand replaces any login, it can't be refreshed
+        User::forget_login(database, account).await?;
+        Ok(claims)
+    }
+    pub async fn get_token(database: &mut Database, account: String) -> BotResult<String> {
+        database.get_token(account).await
+    }
+    pub async fn delete_token(
+        database: &mut Database,
+        user_id: String,
+        profile: &str,
+    ) -> BotResult<()> {
+        let account = account_id(&user_id, profile);
+        database.delete_token(account.clone()).await?;
+        User::forget_login(database, account).await?;
+        if profile != DEFAULT_PROFILE {
+            database
+                .set_profile(user_id.clone(), profile.to_string(), false)
+                .await?;
+        }
+        // Go back to the default profile rather than pointing at one that's gone
+        if User::get_profile(database, user_id.clone()).await? == profile {
+            database.delete_setting(user_id, "profile").await?;
+        }
         Ok(())
     }
-    pub async fn get_token(database: &mut Database, user_id: String) -> BotResult<String> {
-        Ok(database.get_token(user_id).await?)
+
+    // Logs in with the user's Pocket Casts account. Only the refresh token is kept, encrypted,
+    // the password itself is never stored. Returns the account's email.
+    pub async fn login(
+        database: &mut Database,
+        pocket_casts: &PocketCastsClient,
+        user_id: String,
+        profile: &str,
+        email: &str,
+        password: &str,
+    ) -> BotResult<String> {
+        if password.is_empty() {
+            return Err(BotError::new(BotErrorKind::EmptyTokenError));
+        }
+        let session = pocket_casts.login(email, password).await?;
+        let refresh_token = match &session.refresh_token {
+            Some(value) => value.to_string(),
+            None => return Err(BotError::new(BotErrorKind::TypeError)),
+        };
+        let account = account_id(&user_id, profile);
+        let encrypted = crypto::encrypt(refresh_token.as_bytes())?;
+        database
+            .set_refresh_token(account.clone(), encrypted)
+            .await?;
+        User::save_session(database, account, &session).await?;
+        User::add_profile(database, user_id, profile).await?;
+        Ok(session.email.unwrap_or_else(|| email.to_string()))
+    }
+
+    // Returns the account's access token, refreshed first if it came from a login and is about
+    // to expire
+    pub async fn get_fresh_token(
+        database: &mut Database,
+        pocket_casts: &PocketCastsClient,
+        account: String,
+    ) -> BotResult<String> {
+        if database.get_refresh_token(account.clone()).await?.is_none() {
+            return User::get_token(database, account).await;
+        }
+        let expires_at = database
+            .get_setting(account.clone(), "token_expires_at")
+            .await?
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0);
+        // Leave some room for the upload itself
+        if expires_at > unix_time() + TOKEN_REFRESH_MARGIN {
+            return User::get_token(database, account).await;
+        }
+        User::refresh_token(database, pocket_casts, account).await
+    }
+
+    // Gets a new access token right away, e.g. once Pocket Casts stopped accepting the current
+    // one. Tokens set with /auth can't be refreshed.
+    pub async fn refresh_token(
+        database: &mut Database,
+        pocket_casts: &PocketCastsClient,
+        account: String,
+    ) -> BotResult<String> {
+        let refresh_token = match database.get_refresh_token(account.clone()).await? {
+            Some(encrypted) => String::from_utf8(crypto::decrypt(&encrypted)?)?,
+            None => return Err(BotError::new(BotErrorKind::UnauthorizedError)),
+        };
+        let session = pocket_casts.refresh(&refresh_token).await?;
+        if let Some(new_refresh_token) = &session.refresh_token {
+            let encrypted = crypto::encrypt(new_refresh_token.as_bytes())?;
+            database
+                .set_refresh_token(account.clone(), encrypted)
+                .await?;
+        }
+        User::save_session(database, account, &session).await?;
+        Ok(session.access_token)
+    }
+
+    // Token of the profile the user picked with /use
+    pub async fn get_active_token(
+        database: &mut Database,
+        pocket_casts: &PocketCastsClient,
+        user_id: String,
+    ) -> BotResult<String> {
+        let profile = User::get_profile(database, user_id.clone()).await?;
+        User::get_fresh_token(database, pocket_casts, account_id(&user_id, &profile)).await
+    }
+
+    // Profile names are lowercase, "video" and "audio" are taken by request prefixes
+    pub fn parse_profile(name: &str) -> BotResult<String> {
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            return Ok(DEFAULT_PROFILE.to_string());
+        }
+        let profile_regex = regex!(r#"^[a-z0-9_-]{1,32}$"#);
+        if !profile_regex.is_match(&name) || name == "video" || name == "audio" {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        Ok(name)
+    }
+    // The profile used when a request doesn't name one
+    pub async fn get_profile(database: &mut Database, user_id: String) -> BotResult<String> {
+        Ok(database
+            .get_setting(user_id, "profile")
+            .await?
+            .unwrap_or_else(|| DEFAULT_PROFILE.to_string()))
+    }
+    pub async fn set_profile(
+        database: &mut Database,
+        user_id: String,
+        profile: &str,
+    ) -> BotResult<()> {
+        if !User::get_profiles(database, user_id.clone())
+            .await?
+            .iter()
+            .any(|value| value == profile)
+        {
+            return Err(BotError::new(BotErrorKind::UnknownProfileError));
+        }
+        if profile == DEFAULT_PROFILE {
+            database.delete_setting(user_id, "profile").await
+        } else {
+            database
+                .set_setting(user_id, "profile", profile.to_string())
+                .await
+        }
+    }
+    // Profiles with a token, the default one first
+    pub async fn get_profiles(database: &mut Database, user_id: String) -> BotResult<Vec<String>> {
+        let mut profiles = database.get_profiles(user_id.clone()).await?;
+        profiles.sort();
+        if User::get_token(database, user_id).await.is_ok() {
+            profiles.insert(0, DEFAULT_PROFILE.to_string());
+        }
+        Ok(profiles)
+    }
+    async fn add_profile(database: &mut Database, user_id: String, profile: &str) -> BotResult<()> {
+        if profile == DEFAULT_PROFILE {
+            return Ok(());
+        }
+        database
+            .set_profile(user_id, profile.to_string(), true)
+            .await
+    }
+
+    async fn save_session(
+        database: &mut Database,
+        account: String,
+        session: &Session,
+    ) -> BotResult<()> {
+        database
+            .set_token(account.clone(), session.access_token.clone())
+            .await?;
+        // Without an expiry the token is refreshed before every upload
+        let expires_at = unix_time() + session.expires_in.unwrap_or(0);
+        database
+            .set_setting(account, "token_expires_at", expires_at.to_string())
+            .await
+    }
+
+    async fn forget_login(database: &mut Database, account: String) -> BotResult<()> {
+        database.delete_refresh_token(account.clone()).await?;
+        database.delete_setting(account, "token_expires_at").await
+    }
+
+    // Cookies are stored encrypted, they give full access to the user's accounts
+    pub async fn set_cookies(
+        database: &mut Database,
+        user_id: String,
+        cookies: Vec<u8>,
+    ) -> BotResult<()> {
+        let contents = String::from_utf8(cookies)?;
+        // Netscape cookie files have 7 tab separated fields per cookie
+        let has_cookie = contents
+            .lines()
+            .filter(|line| !line.starts_with('#') || line.starts_with("#HttpOnly_"))
+            .any(|line| line.split('\t').count() == 7);
+        if !has_cookie {
+            return Err(BotError::new(BotErrorKind::InvalidCookiesError));
+        }
+        let encrypted = crypto::encrypt(contents.as_bytes())?;
+        database.set_cookies(user_id, encrypted).await
     }
-    pub async fn delete_token(database: &mut Database, user_id: String) -> BotResult<()> {
-        Ok(database.delete_token(user_id).await?)
+    pub async fn get_cookies(
+        database: &mut Database,
+        user_id: String,
+    ) -> BotResult<Option<Vec<u8>>> {
+        match database.get_cookies(user_id).await? {
+            Some(encrypted) => Ok(Some(crypto::decrypt(&encrypted)?)),
+            None => Ok(None),
+        }
+    }
+    pub async fn delete_cookies(database: &mut Database, user_id: String) -> BotResult<()> {
+        database.delete_cookies(user_id).await
+    }
+    pub async fn get_subtitles(
+        database: &mut Database,
+        user_id: String,
+    ) -> BotResult<Option<SubtitlePreference>> {
+        match database.get_setting(user_id, "subtitles").await? {
+            Some(value) => Ok(SubtitlePreference::parse(&value).ok()),
+            None => Ok(None),
+        }
+    }
+    pub async fn set_subtitles(
+        database: &mut Database,
+        user_id: String,
+        preference: Option<SubtitlePreference>,
+    ) -> BotResult<()> {
+        match preference {
+            Some(value) => {
+                database
+                    .set_setting(user_id, "subtitles", value.to_string())
+                    .await
+            }
+            None => database.delete_setting(user_id, "subtitles").await,
+        }
+    }
+    // Whether to upload videos instead of audio, off unless turned on
+    pub async fn get_video(database: &mut Database, user_id: String) -> BotResult<bool> {
+        Ok(database.get_setting(user_id, "video").await?.as_deref() == Some("on"))
+    }
+    pub async fn set_video(database: &mut Database, user_id: String, video: bool) -> BotResult<()> {
+        if video {
+            database
+                .set_setting(user_id, "video", String::from("on"))
+                .await
+        } else {
+            database.delete_setting(user_id, "video").await
+        }
+    }
+    // Where uploads go, Pocket Casts unless the user picked something else
+    pub async fn get_destinations(
+        database: &mut Database,
+        user_id: String,
+    ) -> BotResult<Vec<String>> {
+        match database.get_setting(user_id, "destinations").await? {
+            Some(value) => Ok(value.split(',').map(String::from).collect()),
+            None => Ok(vec![destination::DEFAULT_DESTINATION.to_string()]),
+        }
+    }
+    pub async fn set_destinations(
+        database: &mut Database,
+        user_id: String,
+        destinations: &[String],
+    ) -> BotResult<()> {
+        if destinations.is_empty()
+            || destinations
+                .iter()
+                .any(|id| !destination::DESTINATION_IDS.contains(&id.as_str()))
+        {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        if destinations == [destination::DEFAULT_DESTINATION] {
+            database.delete_setting(user_id, "destinations").await
+        } else {
+            database
+                .set_setting(user_id, "destinations", destinations.join(","))
+                .await
+        }
+    }
+    // Stops sending uploads to a destination, they go to Pocket Casts if nothing else is left
+    pub async fn remove_destination(
+        database: &mut Database,
+        user_id: String,
+        id: &str,
+    ) -> BotResult<()> {
+        let mut destinations: Vec<String> = Self::get_destinations(database, user_id.clone())
+            .await?
+            .into_iter()
+            .filter(|destination| destination != id)
+            .collect();
+        if destinations.is_empty() {
+            destinations.push(destination::DEFAULT_DESTINATION.to_string());
+        }
+        Self::set_destinations(database, user_id, &destinations).await
+    }
+    // Settings for destinations such as S3 or WebDAV, stored encrypted as they hold passwords
+    pub async fn get_destination_config<T: DeserializeOwned>(
+        database: &mut Database,
+        user_id: String,
+        destination: &str,
+    ) -> BotResult<Option<T>> {
+        let encrypted = match database
+            .get_destination_config(user_id, destination)
+            .await?
+        {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match serde_json::from_slice(&crypto::decrypt(&encrypted)?) {
+            Ok(config) => Ok(Some(config)),
+            Err(_) => Err(BotError::new(BotErrorKind::TypeError)),
+        }
+    }
+    pub async fn set_destination_config<T: Serialize>(
+        database: &mut Database,
+        user_id: String,
+        destination: &str,
+        config: Option<&T>,
+    ) -> BotResult<()> {
+        let config = match config {
+            Some(value) => value,
+            None => {
+                return database
+                    .delete_destination_config(user_id, destination)
+                    .await
+            }
+        };
+        let value = match serde_json::to_vec(config) {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::new(BotErrorKind::TypeError)),
+        };
+        let encrypted = crypto::encrypt(&value)?;
+        database
+            .set_destination_config(user_id, destination, encrypted)
+            .await
+    }
+    pub async fn get_up_next(
+        database: &mut Database,
+        user_id: String,
+    ) -> BotResult<Option<UpNextPosition>> {
+        Ok(database
+            .get_setting(user_id, "up_next")
+            .await?
+            .as_deref()
+            .and_then(UpNextPosition::parse))
+    }
+    pub async fn set_up_next(
+        database: &mut Database,
+        user_id: String,
+        position: Option<UpNextPosition>,
+    ) -> BotResult<()> {
+        match position {
+            Some(value) => {
+                database
+                    .set_setting(user_id, "up_next", value.as_str().to_string())
+                    .await
+            }
+            None => database.delete_setting(user_id, "up_next").await,
+        }
+    }
+    pub async fn get_title_format(
+        database: &mut Database,
+        user_id: String,
+    ) -> BotResult<TitleFormat> {
+        let mut format = TitleFormat::default();
+        if let Some(template) = database
+            .get_setting(user_id.clone(), "title_template")
+            .await?
+        {
+            format.template = template;
+        }
+        format.filters = User::get_title_filters(database, user_id).await?;
+        Ok(format)
+    }
+    // None goes back to the default template
+    pub async fn set_title_template(
+        database: &mut Database,
+        user_id: String,
+        template: Option<String>,
+    ) -> BotResult<()> {
+        match template {
+            Some(value) => {
+                TitleFormat::validate_template(&value)?;
+                database.set_setting(user_id, "title_template", value).await
+            }
+            None => database.delete_setting(user_id, "title_template").await,
+        }
+    }
+    pub async fn get_title_filters(
+        database: &mut Database,
+        user_id: String,
+    ) -> BotResult<Vec<TitleFilter>> {
+        match database.get_setting(user_id, "title_filters").await? {
+            Some(value) => match serde_json::from_str(&value) {
+                Ok(filters) => Ok(filters),
+                Err(_) => Err(BotError::new(BotErrorKind::TypeError)),
+            },
+            None => Ok(Vec::new()),
+        }
+    }
+    pub async fn set_title_filters(
+        database: &mut Database,
+        user_id: String,
+        filters: Vec<TitleFilter>,
+    ) -> BotResult<()> {
+        if filters.is_empty() {
+            return database.delete_setting(user_id, "title_filters").await;
+        }
+        if filters.len() > MAX_FILTERS {
+            return Err(BotError::new(BotErrorKind::InvalidSettingError));
+        }
+        let value = match serde_json::to_string(&filters) {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::new(BotErrorKind::TypeError)),
+        };
+        database.set_setting(user_id, "title_filters", value).await
+    }
+    // Days after which files are deleted, 0 to only delete played files. None when
+    // housekeeping is off.
+    pub async fn get_housekeeping(
+        database: &mut Database,
+        user_id: String,
+    ) -> BotResult<Option<u64>> {
+        match database.get_setting(user_id, "housekeeping_days").await? {
+            Some(value) => Ok(value.parse::<u64>().ok()),
+            None => Ok(None),
+        }
+    }
+    pub async fn set_housekeeping(
+        database: &mut Database,
+        user_id: String,
+        max_age_days: Option<u64>,
+    ) -> BotResult<()> {
+        database
+            .set_housekeeping_user(user_id.clone(), max_age_days.is_some())
+            .await?;
+        match max_age_days {
+            Some(value) => {
+                database
+                    .set_setting(user_id, "housekeeping_days", value.to_string())
+                    .await
+            }
+            None => database.delete_setting(user_id, "housekeeping_days").await,
+        }
     }
 }
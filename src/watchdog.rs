@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use teloxide::{
+    requests::Requester,
+    types::{ChatId, UserId},
+    Bot,
+};
+
+// Updated whenever a job makes progress, e.g. yt-dlp printing a line of output
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    last_beat: Arc<Mutex<Instant>>,
+}
+
+impl Heartbeat {
+    fn new() -> Self {
+        Heartbeat {
+            last_beat: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    pub fn beat(&self) {
+        *self.last_beat.lock().unwrap() = Instant::now();
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.last_beat.lock().unwrap().elapsed()
+    }
+}
+
+struct WatchedJob {
+    user_id: String,
+    url: String,
+    heartbeat: Heartbeat,
+    // Admins only hear about each stalled job once
+    reported: bool,
+}
+
+// Keeps an eye on running jobs and lets admins know about ones that stopped making progress
+#[derive(Clone, Default)]
+pub struct Watchdog {
+    jobs: Arc<Mutex<HashMap<String, WatchedJob>>>,
+}
+
+impl Watchdog {
+    pub fn watch(&self, processing_id: &str, user_id: &str, url: &str) -> Heartbeat {
+        let heartbeat = Heartbeat::new();
+        self.jobs.lock().unwrap().insert(
+            processing_id.to_string(),
+            WatchedJob {
+                user_id: user_id.to_string(),
+                url: url.to_string(),
+                heartbeat: heartbeat.clone(),
+                reported: false,
+            },
+        );
+        heartbeat
+    }
+
+    pub fn unwatch(&self, processing_id: &str) {
+        self.jobs.lock().unwrap().remove(processing_id);
+    }
+
+    pub fn start(&self, bot: Bot, admin_user_ids: Vec<UserId>, stall_after: Duration) {
+        let watchdog = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                for report in watchdog.stalled_jobs(stall_after) {
+                    for admin in admin_user_ids.iter() {
+                        let chat_id = ChatId(admin.0 as i64);
+                        bot.send_message(chat_id, report.clone()).await.ok();
+                    }
+                }
+            }
+        });
+    }
+
+    fn stalled_jobs(&self, stall_after: Duration) -> Vec<String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.iter_mut()
+            .filter(|(_, job)| !job.reported && job.heartbeat.elapsed() >= stall_after)
+            .map(|(processing_id, job)| {
+                job.reported = true;
+                format!(
+                    "Job {} has made no progress for {} minutes.\nUser Id: {}\nUrl: {}",
+                    processing_id,
+                    job.heartbeat.elapsed().as_secs() / 60,
+                    job.user_id,
+                    job.url
+                )
+            })
+            .collect()
+    }
+}